@@ -0,0 +1,220 @@
+/* SPDX-License-Identifier: MIT
+* Copyright (c) 2024 Elieva Pignat, Florian Depraz, Louis Mayencourt
+*/
+
+use std::f32::consts::TAU;
+use std::ops::DerefMut;
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::GameState;
+use crate::input::{Action, GameInput};
+use crate::history::History;
+use crate::menu::RestartGame;
+use crate::world::grid::{CellState, FieldGrid};
+
+/// Copies of every cut made by the symmetry assist
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub enum SymmetryAssist {
+    #[default]
+    Off,
+    /// Mirrored between the left and the right of the field
+    MirrorX,
+    /// Mirrored between the top and the bottom of the field
+    MirrorY,
+    /// Copies rotated around the center of the field
+    Rotational(u32),
+}
+
+impl SymmetryAssist {
+    /// Modes in the order they are selected
+    const MODES: [SymmetryAssist; 6] = [
+        SymmetryAssist::Off,
+        SymmetryAssist::MirrorX,
+        SymmetryAssist::MirrorY,
+        SymmetryAssist::Rotational(2),
+        SymmetryAssist::Rotational(4),
+        SymmetryAssist::Rotational(8),
+    ];
+
+    pub fn name(&self) -> String {
+        match self {
+            Self::Off => "Off".to_string(),
+            Self::MirrorX => "Mirror left/right".to_string(),
+            Self::MirrorY => "Mirror top/bottom".to_string(),
+            Self::Rotational(folds) => format!("{}-fold", folds),
+        }
+    }
+
+    fn next(&self) -> Self {
+        let idx = Self::MODES.iter().position(|mode| mode == self).unwrap_or(0);
+        Self::MODES[(idx + 1) % Self::MODES.len()]
+    }
+
+    /// The cells and all their copies, copies outside of the field are kept and ignored when cutting
+    pub fn apply(&self, cells: Vec<IVec2>, field: &FieldGrid) -> Vec<IVec2> {
+        let center = Vec2::new(field.width() as f32 - 1.0, field.height() as f32 - 1.0) / 2.0;
+
+        let mut copies = cells.clone();
+        for cell in cells {
+            let offset = cell.as_vec2() - center;
+            match *self {
+                Self::Off => (),
+                Self::MirrorX => copies.push((center + Vec2::new(-offset.x, offset.y)).round().as_ivec2()),
+                Self::MirrorY => copies.push((center + Vec2::new(offset.x, -offset.y)).round().as_ivec2()),
+                Self::Rotational(folds) => {
+                    for fold in 1..folds {
+                        let rotation = Vec2::from_angle(TAU * fold as f32 / folds as f32);
+                        copies.push((center + rotation.rotate(offset)).round().as_ivec2());
+                    }
+                }
+            }
+        }
+        copies
+    }
+}
+
+/// Symmetry assist selected by the player
+#[derive(Resource, Default)]
+pub struct Assist {
+    pub mode: SymmetryAssist,
+    /// Modes used to cut during the level, in the order they were first used, kept in the score
+    pub used: Vec<SymmetryAssist>,
+}
+
+impl Assist {
+    /// Cut the cells of the player and their copies, shared by the brush, the tools and the pointer.
+    /// Only touch the field if there is corn, to not trigger a resync of the view.
+    /// Returns the cells that were cut.
+    pub fn cut(
+        &mut self,
+        cells: Vec<IVec2>,
+        history: &mut History,
+        field: &mut impl DerefMut<Target = FieldGrid>,
+    ) -> Vec<IVec2> {
+        let cells = self.mode.apply(cells, field);
+        if !cells.iter().any(|cell| field.get(*cell) == Some(CellState::Standing)) {
+            return Vec::new();
+        }
+        let cut = history.cut(field, cells);
+        if !cut.is_empty() {
+            self.record_use();
+        }
+        cut
+    }
+
+    /// Keep the current mode in the score, once a cut with it changed the field
+    pub fn record_use(&mut self) {
+        if self.mode != SymmetryAssist::Off && !self.used.contains(&self.mode) {
+            self.used.push(self.mode);
+        }
+    }
+}
+
+pub struct AssistPlugin;
+
+impl Plugin for AssistPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Assist>();
+        app.add_systems(Update, (reset_assist, select_assist).chain());
+    }
+}
+
+/// Switch to the next symmetry
+fn select_assist(
+    input: GameInput,
+    state: Res<State<GameState>>,
+    mut assist: ResMut<Assist>,
+) {
+    if *state.get() == GameState::InGame && input.just_pressed(Action::Symmetry) {
+        assist.mode = assist.mode.next();
+    }
+}
+
+/// The assist stays selected between levels, but it is only recorded once used
+fn reset_assist(
+    mut event: EventReader<RestartGame>,
+    mut assist: ResMut<Assist>,
+) {
+    if !event.is_empty() {
+        event.clear();
+        assist.used.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sorted(mut cells: Vec<IVec2>) -> Vec<IVec2> {
+        cells.sort_by_key(|cell| (cell.x, cell.y));
+        cells
+    }
+
+    #[test]
+    fn off_keeps_the_cells() {
+        let field = FieldGrid::new(5, 5);
+        let cells = vec![IVec2::new(0, 1), IVec2::new(3, 2)];
+        assert_eq!(SymmetryAssist::Off.apply(cells.clone(), &field), cells);
+    }
+
+    #[test]
+    fn mirrors_copy_across_the_center() {
+        let field = FieldGrid::new(5, 5);
+        assert_eq!(
+            SymmetryAssist::MirrorX.apply(vec![IVec2::new(0, 1)], &field),
+            vec![IVec2::new(0, 1), IVec2::new(4, 1)],
+        );
+        assert_eq!(
+            SymmetryAssist::MirrorY.apply(vec![IVec2::new(0, 1)], &field),
+            vec![IVec2::new(0, 1), IVec2::new(0, 3)],
+        );
+    }
+
+    #[test]
+    fn rotational_copies_around_the_center() {
+        let field = FieldGrid::new(5, 5);
+        assert_eq!(
+            sorted(SymmetryAssist::Rotational(4).apply(vec![IVec2::new(0, 1)], &field)),
+            vec![IVec2::new(0, 1), IVec2::new(1, 4), IVec2::new(3, 0), IVec2::new(4, 3)],
+        );
+        assert_eq!(
+            SymmetryAssist::Rotational(2).apply(vec![IVec2::new(0, 1)], &field),
+            vec![IVec2::new(0, 1), IVec2::new(4, 3)],
+        );
+    }
+
+    #[test]
+    fn cut_records_the_mode_once_it_cut_something() {
+        let mut world = World::new();
+        world.insert_resource(FieldGrid::new(5, 5));
+        let mut field = world.resource_mut::<FieldGrid>();
+        let mut history = History::default();
+        let mut assist = Assist {
+            mode: SymmetryAssist::MirrorX,
+            ..default()
+        };
+
+        let cut = assist.cut(vec![IVec2::new(0, 1), IVec2::new(2, 2)], &mut history, &mut field);
+        assert_eq!(cut, vec![IVec2::new(0, 1), IVec2::new(2, 2), IVec2::new(4, 1)]);
+        assert_eq!(assist.used, vec![SymmetryAssist::MirrorX]);
+
+        // The center is its own copy, cutting it again changes nothing and is not recorded
+        assist.mode = SymmetryAssist::MirrorY;
+        assert!(assist.cut(vec![IVec2::new(2, 2)], &mut history, &mut field).is_empty());
+        assert_eq!(assist.used, vec![SymmetryAssist::MirrorX]);
+    }
+
+    #[test]
+    fn only_used_modes_are_recorded() {
+        let mut assist = Assist::default();
+        assist.record_use();
+        assert!(assist.used.is_empty());
+
+        assist.mode = SymmetryAssist::MirrorX;
+        assist.record_use();
+        assist.record_use();
+        assert_eq!(assist.used, vec![SymmetryAssist::MirrorX]);
+    }
+}
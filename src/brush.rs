@@ -0,0 +1,224 @@
+/* SPDX-License-Identifier: MIT
+* Copyright (c) 2024 Elieva Pignat, Florian Depraz, Louis Mayencourt
+*/
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::GameState;
+use crate::menu::{CurrentLevel, RestartGame};
+use crate::input::{Action, GameInput};
+use crate::player::Player;
+use crate::tools::{DrawTool, ToolKind};
+use crate::world::grid::FieldGrid;
+use crate::world::levels::Level;
+
+/// Number of cells cut by the line brush, starting at the cell of the player
+const LINE_BRUSH_LENGTH: i32 = 3;
+
+/// Footprint of the tractor beam
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub enum Brush {
+    /// The cell under the player
+    #[default]
+    Single,
+    /// The cell under the player and its four neighbours
+    Plus,
+    /// The 3x3 square around the player
+    Square,
+    /// A line starting under the player, in the direction it is facing
+    Line,
+}
+
+impl Brush {
+    pub const ALL: [Brush; 4] = [Brush::Single, Brush::Plus, Brush::Square, Brush::Line];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Single => "Single",
+            Self::Plus => "Plus",
+            Self::Square => "Square",
+            Self::Line => "Line",
+        }
+    }
+
+    /// Cells covered by the brush for a player on `cell`, facing `heading`
+    pub fn cells(&self, cell: IVec2, heading: IVec2) -> Vec<IVec2> {
+        match self {
+            Self::Single => vec![cell],
+            Self::Plus => [IVec2::ZERO, IVec2::X, -IVec2::X, IVec2::Y, -IVec2::Y]
+                .iter()
+                .map(|offset| cell + *offset)
+                .collect(),
+            Self::Square => (-1..=1)
+                .flat_map(|x| (-1..=1).map(move |y| cell + IVec2::new(x, y)))
+                .collect(),
+            Self::Line => (0..LINE_BRUSH_LENGTH).map(|i| cell + heading * i).collect(),
+        }
+    }
+}
+
+/// Brush used by the player, among the brushes unlocked by the level
+#[derive(Resource, Default)]
+pub struct SelectedBrush(pub Brush);
+
+/// Component to identify the tiles showing the footprint of the brush
+#[derive(Component)]
+struct BrushPreview;
+
+/// Mesh and material shared by the preview tiles
+#[derive(Resource)]
+struct PreviewAssets {
+    mesh: Handle<Mesh>,
+    material: Handle<StandardMaterial>,
+}
+
+pub struct BrushPlugin;
+
+impl Plugin for BrushPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<SelectedBrush>();
+        app.add_systems(Startup, setup_preview);
+        app.add_systems(Update, (reset_brush, select_brush, preview_brush).chain());
+    }
+}
+
+fn setup_preview(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+) {
+    commands.insert_resource(PreviewAssets {
+        mesh: meshes.add(Cuboid::new(0.9, 0.05, 0.9)),
+        material: materials.add(Color::srgba(1.0, 1.0, 0.0, 0.35)),
+    });
+}
+
+/// Switch to the next brush unlocked by the level
+fn select_brush(
+    input: GameInput,
+    state: Res<State<GameState>>,
+    current_level: Res<CurrentLevel>,
+    levels: Res<Assets<Level>>,
+    mut selected: ResMut<SelectedBrush>,
+) {
+    if *state.get() != GameState::InGame || !input.just_pressed(Action::Brush) {
+        return;
+    }
+    let Some(level) = levels.get(&current_level.handle) else {
+        return;
+    };
+
+    let idx = level.brushes.iter().position(|brush| *brush == selected.0);
+    let next = idx.map_or(0, |idx| (idx + 1) % level.brushes.len());
+    if let Some(brush) = level.brushes.get(next) {
+        selected.0 = *brush;
+    }
+}
+
+/// Every level starts with the first brush it unlocks
+fn reset_brush(
+    mut event: EventReader<RestartGame>,
+    current_level: Res<CurrentLevel>,
+    levels: Res<Assets<Level>>,
+    mut selected: ResMut<SelectedBrush>,
+) {
+    if !event.is_empty() {
+        event.clear();
+        if let Some(level) = levels.get(&current_level.handle) {
+            selected.0 = level.brushes.first().copied().unwrap_or_default();
+        }
+    }
+}
+
+/// Show the cells that the brush would cut, on the ground around the player
+#[allow(clippy::too_many_arguments)]
+fn preview_brush(
+    mut commands: Commands,
+    state: Res<State<GameState>>,
+    player: Query<&Player>,
+    selected: Res<SelectedBrush>,
+    field: Res<FieldGrid>,
+    previews: Query<Entity, With<BrushPreview>>,
+    preview_assets: Res<PreviewAssets>,
+    tool: Res<DrawTool>,
+    mut shown: Local<Option<(IVec2, IVec2, Brush)>>,
+) {
+    let player = player.single();
+    let wanted = if *state.get() == GameState::InGame && tool.kind == ToolKind::Brush {
+        Some((player.cell, player.heading, selected.0))
+    } else {
+        None
+    };
+    if *shown == wanted {
+        return;
+    }
+    *shown = wanted;
+
+    for preview in previews.iter() {
+        commands.entity(preview).despawn();
+    }
+
+    if let Some((cell, heading, brush)) = wanted {
+        for cell in brush.cells(cell, heading) {
+            if field.is_walkable(cell) {
+                commands.spawn((
+                    PbrBundle {
+                        mesh: preview_assets.mesh.clone(),
+                        material: preview_assets.material.clone(),
+                        transform: Transform::from_xyz(cell.x as f32, 0.1, cell.y as f32),
+                        ..default()
+                    },
+                    BrushPreview,
+                ));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::history::History;
+
+    fn sorted(mut cells: Vec<IVec2>) -> Vec<IVec2> {
+        cells.sort_by_key(|cell| (cell.x, cell.y));
+        cells
+    }
+
+    #[test]
+    fn single_and_plus_footprints() {
+        let cell = IVec2::new(3, 3);
+        assert_eq!(Brush::Single.cells(cell, IVec2::Y), vec![cell]);
+        assert_eq!(
+            sorted(Brush::Plus.cells(cell, IVec2::Y)),
+            vec![IVec2::new(2, 3), IVec2::new(3, 2), IVec2::new(3, 3), IVec2::new(3, 4), IVec2::new(4, 3)],
+        );
+    }
+
+    #[test]
+    fn square_footprint() {
+        let cells = Brush::Square.cells(IVec2::new(3, 3), IVec2::X);
+        assert_eq!(cells.len(), 9);
+        assert!(cells.iter().all(|cell| (*cell - IVec2::new(3, 3)).abs().max_element() <= 1));
+    }
+
+    #[test]
+    fn line_follows_the_heading() {
+        let cell = IVec2::new(3, 3);
+        assert_eq!(Brush::Line.cells(cell, IVec2::X), vec![cell, IVec2::new(4, 3), IVec2::new(5, 3)]);
+        assert_eq!(Brush::Line.cells(cell, IVec2::NEG_Y), vec![cell, IVec2::new(3, 2), IVec2::new(3, 1)]);
+        assert_eq!(Brush::Line.cells(cell, IVec2::ONE), vec![cell, IVec2::new(4, 4), IVec2::new(5, 5)]);
+    }
+
+    #[test]
+    fn cells_outside_of_the_field_are_not_cut() {
+        let mut field = FieldGrid::new(4, 4);
+        let mut history = History::default();
+
+        let cut = history.cut(&mut field, Brush::Square.cells(IVec2::ZERO, IVec2::Y));
+        assert_eq!(sorted(cut), vec![IVec2::new(0, 0), IVec2::new(0, 1), IVec2::new(1, 0), IVec2::new(1, 1)]);
+        let cut = history.cut(&mut field, Brush::Line.cells(IVec2::new(3, 2), IVec2::X));
+        assert_eq!(cut, vec![IVec2::new(3, 2)]);
+    }
+}
@@ -4,11 +4,11 @@
 
 use bevy::prelude::*;
 
-use crate::world::levels::LEVELS;
+use crate::world::levels::Level;
 use crate::menu::{CurrentLevel, RestartGame};
 
 #[derive(Component)]
-struct MainCamera;
+pub struct MainCamera;
 
 pub struct GameCameraPlugin;
 
@@ -19,15 +19,14 @@ impl Plugin for GameCameraPlugin {
     }
 }
 
+/// Spawn the camera, it is framed on the level by `update_camera` once the level is loaded
 fn setup_camera(
     mut commands: Commands,
-    current_level: Res<CurrentLevel>,
 ) {
-    let level_size = LEVELS[current_level.idx].grid_size as f32;
     commands.spawn((
         Camera3dBundle {
-            transform: Transform::from_xyz(level_size/2.0, level_size, -level_size/2.0)
-                .looking_at(Vec3::new(level_size / 2.0, 0.0, level_size / 2.0), Vec3::Y),
+            transform: Transform::from_xyz(0.0, 1.0, 0.0)
+                .looking_at(Vec3::ZERO, Vec3::Z),
             ..default()
         },
         MainCamera
@@ -38,12 +37,19 @@ fn update_camera(
     mut query: Query<&mut Transform, With<MainCamera>>,
     event: EventReader<RestartGame>,
     current_level: Res<CurrentLevel>,
+    levels: Res<Assets<Level>>,
 ) {
     if !event.is_empty() {
-        let level_size = LEVELS[current_level.idx].grid_size as f32;
+        let Some(level) = levels.get(&current_level.handle) else {
+            return;
+        };
+        let width = level.pattern.width() as f32;
+        let height = level.pattern.height() as f32;
+        // Step back from the center of the field enough to see its longest side
+        let distance = width.max(height);
         let mut camera = query.single_mut();
         
-        *camera = Transform::from_xyz(level_size/2.0, level_size, -level_size/2.0)
-        .looking_at(Vec3::new(level_size / 2.0, 0.0, level_size / 2.0), Vec3::Y);
+        *camera = Transform::from_xyz(width/2.0, distance, height/2.0 - distance)
+        .looking_at(Vec3::new(width / 2.0, 0.0, height / 2.0), Vec3::Y);
     }
 }
@@ -0,0 +1,48 @@
+/* SPDX-License-Identifier: MIT
+* Copyright (c) 2024 Elieva Pignat, Florian Depraz, Louis Mayencourt
+*/
+
+use std::path::PathBuf;
+
+/// Folder of the game in the configuration folder of the user, `None` when it cannot be found.
+/// `CORN_FIELD_CONFIG_DIR` can be set to use another folder.
+pub fn config_dir() -> Option<PathBuf> {
+    if let Some(dir) = std::env::var_os("CORN_FIELD_CONFIG_DIR") {
+        return Some(PathBuf::from(dir));
+    }
+
+    let base = if cfg!(target_os = "windows") {
+        std::env::var_os("APPDATA").map(PathBuf::from)
+    } else if cfg!(target_os = "macos") {
+        std::env::var_os("HOME").map(|home| PathBuf::from(home).join("Library/Application Support"))
+    } else {
+        std::env::var_os("XDG_CONFIG_HOME")
+            .map(PathBuf::from)
+            .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))
+    };
+    base.map(|base| base.join("corn-field"))
+}
+
+/// Path of a configuration file of the game
+pub fn config_file(name: &str) -> Option<PathBuf> {
+    config_dir().map(|dir| dir.join(name))
+}
+
+/// Folder of the game in the data folder of the user, for the files written by the game itself.
+/// `CORN_FIELD_DATA_DIR` can be set to use another folder.
+pub fn data_dir() -> Option<PathBuf> {
+    if let Some(dir) = std::env::var_os("CORN_FIELD_DATA_DIR") {
+        return Some(PathBuf::from(dir));
+    }
+
+    let base = if cfg!(target_os = "windows") {
+        std::env::var_os("LOCALAPPDATA").map(PathBuf::from)
+    } else if cfg!(target_os = "macos") {
+        std::env::var_os("HOME").map(|home| PathBuf::from(home).join("Library/Application Support"))
+    } else {
+        std::env::var_os("XDG_DATA_HOME")
+            .map(PathBuf::from)
+            .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".local/share")))
+    };
+    base.map(|base| base.join("corn-field"))
+}
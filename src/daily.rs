@@ -0,0 +1,76 @@
+/* SPDX-License-Identifier: MIT
+* Copyright (c) 2024 Elieva Pignat, Florian Depraz, Louis Mayencourt
+*/
+
+use bevy::utils::SystemTime;
+
+use crate::world::generator::PatternGenerator;
+use crate::world::levels::Level;
+
+const SECONDS_PER_DAY: u64 = 24 * 60 * 60;
+
+/// Challenge of the day, everybody playing on the same (UTC) day gets the same field
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DailyChallenge {
+    pub seed: u64,
+    /// Day of the challenge, as YYYY-MM-DD
+    pub date: String,
+}
+
+impl DailyChallenge {
+    pub fn today() -> Self {
+        let days = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map(|elapsed| elapsed.as_secs() / SECONDS_PER_DAY)
+            .unwrap_or_default();
+        Self::from_days(days)
+    }
+
+    /// Challenge of the day `days` after the 1st of January 1970
+    pub fn from_days(days: u64) -> Self {
+        // Civil date from the day number, see http://howardhinnant.github.io/date_algorithms.html
+        let z = days as i64 + 719468;
+        let era = z.div_euclid(146097);
+        let doe = z.rem_euclid(146097);
+        let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+        let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+        let mp = (5 * doy + 2) / 153;
+        let day = doy - (153 * mp + 2) / 5 + 1;
+        let month = if mp < 10 { mp + 3 } else { mp - 9 };
+        let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+
+        Self {
+            seed: days,
+            date: format!("{:04}-{:02}-{:02}", year, month, day),
+        }
+    }
+
+    /// Level of the day, its size changes from day to day
+    pub fn level(&self) -> Level {
+        let size = 21 + 6 * (self.seed % 3) as usize;
+        let generator = PatternGenerator {
+            seed: self.seed,
+            difficulty: 0.5,
+        };
+        Level::generated(generator, size, size)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn from_days_gives_the_date() {
+        assert_eq!(DailyChallenge::from_days(0).date, "1970-01-01");
+        assert_eq!(DailyChallenge::from_days(11017).date, "2000-03-01");
+        assert_eq!(DailyChallenge::from_days(19723).date, "2024-01-01");
+        assert_eq!(DailyChallenge::from_days(19782).date, "2024-02-29");
+    }
+
+    #[test]
+    fn same_day_gives_the_same_challenge() {
+        assert_eq!(DailyChallenge::from_days(19723), DailyChallenge::from_days(19723));
+        assert_ne!(DailyChallenge::from_days(19723).seed, DailyChallenge::from_days(19724).seed);
+    }
+}
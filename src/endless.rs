@@ -0,0 +1,119 @@
+/* SPDX-License-Identifier: MIT
+* Copyright (c) 2024 Elieva Pignat, Florian Depraz, Louis Mayencourt
+*/
+
+use bevy::prelude::*;
+
+use crate::menu::GameScore;
+use crate::world::generator::PatternGenerator;
+use crate::world::levels::Level;
+
+/// Size of the field of the first endless round
+const ENDLESS_START_SIZE: usize = 11;
+/// The field grows every round up to this size
+const ENDLESS_MAX_SIZE: usize = 128;
+
+/// Progress of an endless run, the score is cumulated over all the rounds
+#[derive(Resource, Default, Debug)]
+pub struct EndlessRun {
+    seed: u64,
+    pub round: u32,
+    pub points: u32,
+    pub mistakes: u32,
+    pub forgotten: u32,
+}
+
+impl EndlessRun {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            seed,
+            ..default()
+        }
+    }
+
+    /// Level of the current round, bigger and more complex than the previous one
+    pub fn level(&self) -> Level {
+        let size = (ENDLESS_START_SIZE + 4 * self.round as usize).min(ENDLESS_MAX_SIZE);
+        let generator = PatternGenerator {
+            seed: self.seed.wrapping_add(self.round as u64),
+            difficulty: (self.round as f32 * 0.15).min(1.0),
+        };
+        Level::generated(generator, size, size)
+    }
+
+    /// Add the score of the round to the run.
+    /// Every cell of the formation cut earns a point, every mistake costs one.
+    pub fn record(&mut self, level: &Level, score: &GameScore) {
+        let cut = level.cells_to_cut() as u32 - score.forgotten.min(level.cells_to_cut() as u32);
+        self.points += cut.saturating_sub(score.mistakes);
+        self.mistakes += score.mistakes;
+        self.forgotten += score.forgotten;
+    }
+
+    /// Record the round and go to the next one if it was passed.
+    /// Returns false when the run is over.
+    pub fn finish_round(&mut self, level: &Level, score: &GameScore) -> bool {
+        self.record(level, score);
+        if score.passed() {
+            self.round += 1;
+        }
+        score.passed()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn score(mistakes: u32, forgotten: u32, stars: u32) -> GameScore {
+        GameScore {
+            mistakes,
+            forgotten,
+            stars,
+            ..default()
+        }
+    }
+
+    #[test]
+    fn rounds_grow_up_to_the_max_size() {
+        let mut run = EndlessRun::new(3);
+        assert_eq!(run.level().pattern.width(), 11);
+        run.round = 1;
+        assert_eq!(run.level().pattern.width(), 15);
+        run.round = 29;
+        assert_eq!(run.level().pattern.height(), 127);
+        run.round = 30;
+        assert_eq!(run.level().pattern.width(), ENDLESS_MAX_SIZE);
+        run.round = 100;
+        assert_eq!(run.level().pattern.width(), ENDLESS_MAX_SIZE);
+    }
+
+    #[test]
+    fn points_add_up_over_the_rounds() {
+        let mut run = EndlessRun::new(3);
+        let level = run.level();
+        let to_cut = level.cells_to_cut() as u32;
+
+        run.record(&level, &score(0, 0, 3));
+        assert_eq!(run.points, to_cut);
+        run.record(&level, &score(2, 3, 1));
+        assert_eq!(run.points, 2 * to_cut - 5);
+        assert_eq!(run.mistakes, 2);
+        assert_eq!(run.forgotten, 3);
+
+        // A round never takes points away
+        run.record(&level, &score(3 * to_cut, to_cut, 0));
+        assert_eq!(run.points, 2 * to_cut - 5);
+    }
+
+    #[test]
+    fn run_ends_on_a_failed_round() {
+        let mut run = EndlessRun::new(3);
+        let level = run.level();
+
+        assert!(run.finish_round(&level, &score(0, 0, 1)));
+        assert_eq!(run.round, 1);
+        assert!(!run.finish_round(&level, &score(0, 0, 0)));
+        assert_eq!(run.round, 1);
+    }
+}
@@ -0,0 +1,291 @@
+/* SPDX-License-Identifier: MIT
+* Copyright (c) 2024 Elieva Pignat, Florian Depraz, Louis Mayencourt
+*/
+
+use std::collections::VecDeque;
+
+use bevy::prelude::*;
+
+use crate::GameState;
+use crate::input::{Action, GameInput};
+use crate::menu::{CurrentLevel, RestartGame};
+use crate::world::CellChanged;
+use crate::world::grid::{CellState, FieldGrid};
+use crate::world::levels::Level;
+
+/// Number of actions kept by default in the history
+const DEFAULT_HISTORY_DEPTH: usize = 200;
+
+/// Change of the state of a cell done by the player
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FieldAction {
+    pub cell: IVec2,
+    pub from: CellState,
+    pub to: CellState,
+}
+
+impl FieldAction {
+    pub fn cut(cell: IVec2) -> Self {
+        Self {
+            cell,
+            from: CellState::Standing,
+            to: CellState::Cut,
+        }
+    }
+
+    pub fn replant(cell: IVec2) -> Self {
+        Self {
+            cell,
+            from: CellState::Cut,
+            to: CellState::Standing,
+        }
+    }
+
+    fn reverse(&self) -> Self {
+        Self {
+            cell: self.cell,
+            from: self.to,
+            to: self.from,
+        }
+    }
+
+    /// Apply the action to the field, only if the cell is in the expected state
+    fn apply(&self, field: &mut FieldGrid) -> bool {
+        if field.get(self.cell) == Some(self.from) {
+            field.set(self.cell, self.to);
+            true
+        } else {
+            false
+        }
+    }
+}
+
+/// Actions done by the player since the start of the level, which can be undone and redone.
+/// A step groups all the cells changed by a single action of the player.
+#[derive(Resource)]
+pub struct History {
+    done: VecDeque<Vec<FieldAction>>,
+    undone: Vec<Vec<FieldAction>>,
+    /// Maximum number of steps which can be undone
+    pub max_depth: usize,
+    /// Mistakes added to the score for every undo, set by the level
+    pub undo_penalty: u32,
+    /// Number of undos since the start of the level
+    pub undos: u32,
+}
+
+impl Default for History {
+    fn default() -> Self {
+        Self {
+            done: VecDeque::new(),
+            undone: Vec::new(),
+            max_depth: DEFAULT_HISTORY_DEPTH,
+            undo_penalty: 0,
+            undos: 0,
+        }
+    }
+}
+
+impl History {
+    /// Cut the standing corn of the cells and record it as a single step.
+    /// Returns the cells that were cut.
+    pub fn cut(&mut self, field: &mut FieldGrid, cells: impl IntoIterator<Item = IVec2>) -> Vec<IVec2> {
+        let step: Vec<FieldAction> = cells.into_iter()
+            .map(FieldAction::cut)
+            .filter(|action| action.apply(field))
+            .collect();
+        let cells = step.iter().map(|action| action.cell).collect();
+        self.record(step);
+        cells
+    }
+
+    /// Apply an action to the field and record it, returns false if the cell was not in the expected state
+    pub fn apply(&mut self, field: &mut FieldGrid, action: FieldAction) -> bool {
+        if action.apply(field) {
+            self.record(vec![action]);
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Record a step done by the player, the undone steps cannot be redone anymore
+    fn record(&mut self, step: Vec<FieldAction>) {
+        if step.is_empty() {
+            return;
+        }
+        self.undone.clear();
+        self.done.push_back(step);
+        while self.done.len() > self.max_depth {
+            self.done.pop_front();
+        }
+    }
+
+    /// Revert the last step, returns the cells that changed
+    pub fn undo(&mut self, field: &mut FieldGrid) -> Vec<IVec2> {
+        let Some(step) = self.done.pop_back() else {
+            return Vec::new();
+        };
+        if step.iter().any(|action| field.get(action.cell) != Some(action.to)) {
+            // The field does not match the history anymore, it cannot be trusted
+            self.clear();
+            return Vec::new();
+        }
+        for action in step.iter().rev() {
+            action.reverse().apply(field);
+        }
+        let cells = step.iter().map(|action| action.cell).collect();
+        self.undone.push(step);
+        self.undos += 1;
+        cells
+    }
+
+    /// Apply again the last undone step, returns the cells that changed
+    pub fn redo(&mut self, field: &mut FieldGrid) -> Vec<IVec2> {
+        let Some(step) = self.undone.pop() else {
+            return Vec::new();
+        };
+        if step.iter().any(|action| field.get(action.cell) != Some(action.from)) {
+            self.undone.clear();
+            return Vec::new();
+        }
+        for action in step.iter() {
+            action.apply(field);
+        }
+        let cells = step.iter().map(|action| action.cell).collect();
+        self.done.push_back(step);
+        cells
+    }
+
+    pub fn clear(&mut self) {
+        self.done.clear();
+        self.undone.clear();
+    }
+}
+
+pub struct HistoryPlugin;
+
+impl Plugin for HistoryPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<History>();
+        app.add_systems(Update, (reset_history, undo_redo).chain());
+    }
+}
+
+/// Undo the last action, or redo it
+fn undo_redo(
+    input: GameInput,
+    state: Res<State<GameState>>,
+    mut history: ResMut<History>,
+    mut field: ResMut<FieldGrid>,
+    mut changed: EventWriter<CellChanged>,
+) {
+    if *state.get() != GameState::InGame {
+        return;
+    }
+
+    let cells = if input.just_pressed(Action::Undo) {
+        history.undo(&mut field)
+    } else if input.just_pressed(Action::Redo) {
+        history.redo(&mut field)
+    } else {
+        Vec::new()
+    };
+
+    changed.send_batch(cells.into_iter().map(|cell| CellChanged { cell }));
+}
+
+fn reset_history(
+    mut event: EventReader<RestartGame>,
+    mut history: ResMut<History>,
+    current_level: Res<CurrentLevel>,
+    levels: Res<Assets<Level>>,
+) {
+    if !event.is_empty() {
+        event.clear();
+        history.clear();
+        history.undos = 0;
+        history.undo_penalty = levels.get(&current_level.handle).map_or(0, |level| level.undo_penalty);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cell(x: i32) -> IVec2 {
+        IVec2::new(x, 0)
+    }
+
+    #[test]
+    fn undo_and_redo_a_cut() {
+        let mut field = FieldGrid::new(4, 1);
+        let mut history = History::default();
+
+        assert_eq!(history.cut(&mut field, [cell(0), cell(1)]), vec![cell(0), cell(1)]);
+        assert_eq!(history.undo(&mut field), vec![cell(0), cell(1)]);
+        assert_eq!(field.get(cell(0)), Some(CellState::Standing));
+        assert_eq!(field.get(cell(1)), Some(CellState::Standing));
+        assert_eq!(history.undos, 1);
+
+        assert_eq!(history.redo(&mut field), vec![cell(0), cell(1)]);
+        assert_eq!(field.get(cell(0)), Some(CellState::Cut));
+        assert_eq!(field.get(cell(1)), Some(CellState::Cut));
+        assert!(history.redo(&mut field).is_empty());
+    }
+
+    #[test]
+    fn cells_already_cut_are_not_recorded() {
+        let mut field = FieldGrid::new(4, 1);
+        let mut history = History::default();
+
+        history.cut(&mut field, [cell(0)]);
+        assert!(history.cut(&mut field, [cell(0)]).is_empty());
+        assert_eq!(history.undo(&mut field), vec![cell(0)]);
+        assert!(history.undo(&mut field).is_empty());
+    }
+
+    #[test]
+    fn new_action_drops_the_undone_steps() {
+        let mut field = FieldGrid::new(4, 1);
+        let mut history = History::default();
+
+        history.cut(&mut field, [cell(0)]);
+        history.undo(&mut field);
+        assert!(history.apply(&mut field, FieldAction::cut(cell(1))));
+        assert!(history.redo(&mut field).is_empty());
+        assert_eq!(field.get(cell(0)), Some(CellState::Standing));
+    }
+
+    #[test]
+    fn only_max_depth_steps_can_be_undone() {
+        let mut field = FieldGrid::new(4, 1);
+        let mut history = History {
+            max_depth: 2,
+            ..default()
+        };
+
+        for x in 0..3 {
+            history.cut(&mut field, [cell(x)]);
+        }
+        assert_eq!(history.undo(&mut field), vec![cell(2)]);
+        assert_eq!(history.undo(&mut field), vec![cell(1)]);
+        assert!(history.undo(&mut field).is_empty());
+        assert_eq!(field.get(cell(0)), Some(CellState::Cut));
+
+        assert_eq!(history.redo(&mut field), vec![cell(1)]);
+        assert_eq!(history.redo(&mut field), vec![cell(2)]);
+        assert_eq!(history.undo(&mut field), vec![cell(2)]);
+    }
+
+    #[test]
+    fn undo_is_refused_when_the_field_changed() {
+        let mut field = FieldGrid::new(4, 1);
+        let mut history = History::default();
+
+        history.cut(&mut field, [cell(0)]);
+        field.set(cell(0), CellState::Standing);
+        assert!(history.undo(&mut field).is_empty());
+        assert_eq!(field.get(cell(0)), Some(CellState::Standing));
+    }
+}
@@ -0,0 +1,416 @@
+/* SPDX-License-Identifier: MIT
+* Copyright (c) 2024 Elieva Pignat, Florian Depraz, Louis Mayencourt
+*/
+
+use std::collections::{HashMap, HashSet};
+use std::f32::consts::FRAC_PI_4;
+
+use bevy::{ecs::system::SystemParam, prelude::*};
+use serde::{Deserialize, Serialize};
+
+use crate::save::config_storage;
+
+/// Stick positions closer to the center than this are ignored
+const STICK_DEAD_ZONE: f32 = 0.35;
+
+/// File where the bindings are saved, in the configuration folder or in the browser storage
+const BINDINGS_FILE: &str = "bindings.ron";
+
+/// Everything the player can do with the keyboard or a gamepad
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Deserialize, Serialize)]
+pub enum Action {
+    MoveUp,
+    MoveDown,
+    MoveLeft,
+    MoveRight,
+    Cut,
+    Replant,
+    Brush,
+    Tool,
+    CancelTool,
+    Symmetry,
+    Undo,
+    Redo,
+    Finish,
+    Pause,
+    Endless,
+    Daily,
+    LevelSelect,
+    Settings,
+}
+
+impl Action {
+    /// All the actions, in the order of the settings screen
+    pub const ALL: [Action; 18] = [
+        Action::MoveUp,
+        Action::MoveDown,
+        Action::MoveLeft,
+        Action::MoveRight,
+        Action::Cut,
+        Action::Replant,
+        Action::Brush,
+        Action::Tool,
+        Action::CancelTool,
+        Action::Symmetry,
+        Action::Undo,
+        Action::Redo,
+        Action::Finish,
+        Action::Pause,
+        Action::Endless,
+        Action::Daily,
+        Action::LevelSelect,
+        Action::Settings,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::MoveUp => "Move up",
+            Self::MoveDown => "Move down",
+            Self::MoveLeft => "Move left",
+            Self::MoveRight => "Move right",
+            Self::Cut => "Cut",
+            Self::Replant => "Replant",
+            Self::Brush => "Next brush",
+            Self::Tool => "Line/circle tool",
+            Self::CancelTool => "Cancel line/circle",
+            Self::Symmetry => "Symmetry",
+            Self::Undo => "Undo",
+            Self::Redo => "Redo",
+            Self::Finish => "Finish / continue",
+            Self::Pause => "Pause",
+            Self::Endless => "Endless mode",
+            Self::Daily => "Daily challenge",
+            Self::LevelSelect => "Level select",
+            Self::Settings => "Settings",
+        }
+    }
+
+    /// The action is read on this screen, actions of the same screen cannot share a key or a button
+    fn read_on(&self, screen: InputScreen) -> bool {
+        match self {
+            Self::MoveUp | Self::MoveDown | Self::MoveLeft | Self::MoveRight => true,
+            Self::Endless | Self::Daily | Self::LevelSelect | Self::Settings => screen == InputScreen::LandingScreen,
+            _ => screen == InputScreen::Game,
+        }
+    }
+
+    /// Both actions are read on the same screen
+    fn conflicts_with(&self, other: Action) -> bool {
+        *self != other && InputScreen::ALL.iter().any(|screen| self.read_on(*screen) && other.read_on(*screen))
+    }
+
+    /// The gamepad has fewer buttons than there are actions, so some buttons are shared
+    /// between actions that are never read in the same state:
+    /// West is CancelTool in game and Endless on the landing screen,
+    /// North is Symmetry in game and Daily on the landing screen,
+    /// RightTrigger is Brush in game and LevelSelect on the landing screen,
+    /// Select is Pause in game and Settings on the landing screen.
+    fn default_binding(&self) -> Binding {
+        let (key, button) = match self {
+            Self::MoveUp => (KeyCode::ArrowUp, GamepadButtonType::DPadUp),
+            Self::MoveDown => (KeyCode::ArrowDown, GamepadButtonType::DPadDown),
+            Self::MoveLeft => (KeyCode::ArrowLeft, GamepadButtonType::DPadLeft),
+            Self::MoveRight => (KeyCode::ArrowRight, GamepadButtonType::DPadRight),
+            Self::Cut => (KeyCode::Space, GamepadButtonType::South),
+            Self::Replant => (KeyCode::KeyR, GamepadButtonType::East),
+            Self::Brush => (KeyCode::KeyB, GamepadButtonType::RightTrigger),
+            Self::Tool => (KeyCode::KeyT, GamepadButtonType::LeftTrigger),
+            Self::CancelTool => (KeyCode::Backspace, GamepadButtonType::West),
+            Self::Symmetry => (KeyCode::KeyM, GamepadButtonType::North),
+            Self::Undo => (KeyCode::KeyZ, GamepadButtonType::LeftTrigger2),
+            Self::Redo => (KeyCode::KeyX, GamepadButtonType::RightTrigger2),
+            Self::Finish => (KeyCode::Enter, GamepadButtonType::Start),
+            Self::Pause => (KeyCode::Escape, GamepadButtonType::Select),
+            Self::Endless => (KeyCode::KeyE, GamepadButtonType::West),
+            Self::Daily => (KeyCode::KeyD, GamepadButtonType::North),
+            Self::LevelSelect => (KeyCode::KeyL, GamepadButtonType::RightTrigger),
+            Self::Settings => (KeyCode::KeyS, GamepadButtonType::Select),
+        };
+        Binding {
+            keys: vec![key],
+            buttons: vec![button],
+        }
+    }
+}
+
+/// Screens where the actions are read. The menus only read the moves, finish and pause,
+/// like the game.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum InputScreen {
+    Game,
+    LandingScreen,
+}
+
+impl InputScreen {
+    const ALL: [InputScreen; 2] = [InputScreen::Game, InputScreen::LandingScreen];
+}
+
+/// Keys and gamepad buttons doing an action
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub struct Binding {
+    pub keys: Vec<KeyCode>,
+    pub buttons: Vec<GamepadButtonType>,
+}
+
+impl Binding {
+    /// Names of the keys and buttons, for the settings screen
+    pub fn describe(&self) -> String {
+        let names: Vec<String> = self.keys.iter().map(|key| format!("{:?}", key))
+            .chain(self.buttons.iter().map(|button| format!("Pad {:?}", button)))
+            .collect();
+        if names.is_empty() {
+            "-".to_string()
+        } else {
+            names.join(", ")
+        }
+    }
+}
+
+/// Keys and buttons bound to every action, saved in the configuration folder
+#[derive(Resource, Clone, Debug, Deserialize, Serialize)]
+pub struct Bindings(HashMap<Action, Binding>);
+
+impl Default for Bindings {
+    fn default() -> Self {
+        Self(Action::ALL.iter().map(|action| (*action, action.default_binding())).collect())
+    }
+}
+
+impl Bindings {
+    pub fn get(&self, action: Action) -> &Binding {
+        // Every action is bound by `default` and `load`
+        &self.0[&action]
+    }
+
+    /// Short name of the first key of the action, for the help texts
+    pub fn key_name(&self, action: Action) -> String {
+        match self.get(action).keys.first() {
+            Some(key) => {
+                let name = format!("{:?}", key);
+                name.strip_prefix("Key").or(name.strip_prefix("Digit")).unwrap_or(&name).to_string()
+            }
+            None => "-".to_string(),
+        }
+    }
+
+    /// Bind the keys and buttons to the action, in place of its own. The other actions read on
+    /// the same screen lose these keys and buttons, they are returned to tell the player.
+    pub fn set(&mut self, action: Action, binding: Binding) -> Vec<Action> {
+        let mut cleared = Vec::new();
+        for other in Action::ALL.into_iter().filter(|other| other.conflicts_with(action)) {
+            let Some(other_binding) = self.0.get_mut(&other) else {
+                continue;
+            };
+            let before = (other_binding.keys.len(), other_binding.buttons.len());
+            other_binding.keys.retain(|key| !binding.keys.contains(key));
+            other_binding.buttons.retain(|button| !binding.buttons.contains(button));
+            if before != (other_binding.keys.len(), other_binding.buttons.len()) {
+                cleared.push(other);
+            }
+        }
+        self.0.insert(action, binding);
+        cleared
+    }
+
+    /// Bindings of the configuration file, the actions missing from the file keep their default binding
+    pub fn load() -> Self {
+        let mut bindings = Self::default();
+        let Some(text) = config_storage(BINDINGS_FILE).and_then(|storage| storage.read()) else {
+            return bindings;
+        };
+
+        match ron::de::from_str::<Bindings>(&text) {
+            Ok(saved) => bindings.0.extend(saved.0),
+            Err(err) => warn!("Ignoring the saved bindings: {}", err),
+        }
+        bindings
+    }
+
+    pub fn save(&self) {
+        let Some(storage) = config_storage(BINDINGS_FILE) else {
+            return;
+        };
+        let result = ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::default())
+            .map_err(|err| err.to_string())
+            .and_then(|text| storage.write(&text));
+        if let Err(err) = result {
+            error!("Could not save the bindings: {}", err);
+        }
+    }
+}
+
+/// Actions held with the on-screen buttons and the pointer, refreshed every frame
+#[derive(Resource, Default)]
+pub struct VirtualInput {
+    pressed: HashSet<Action>,
+    previous: HashSet<Action>,
+}
+
+impl VirtualInput {
+    /// Forget the actions of the previous frame, before they are pressed again
+    pub fn begin_frame(&mut self) {
+        self.previous = std::mem::take(&mut self.pressed);
+    }
+
+    pub fn press(&mut self, action: Action) {
+        self.pressed.insert(action);
+    }
+
+    fn pressed(&self, action: Action) -> bool {
+        self.pressed.contains(&action)
+    }
+
+    fn just_pressed(&self, action: Action) -> bool {
+        self.pressed.contains(&action) && !self.previous.contains(&action)
+    }
+}
+
+/// Directions pushed since the previous frame with the move actions or the left stick,
+/// to move once per push in the menus. Kept in a `Local` of the menu system.
+#[derive(Default)]
+pub struct MenuDirections(Vec<IVec2>);
+
+impl MenuDirections {
+    pub fn just_pressed(&mut self, input: &GameInput) -> Vec<IVec2> {
+        let held = input.directions();
+        let pushed = held.iter().filter(|direction| !self.0.contains(direction)).copied().collect();
+        self.0 = held;
+        pushed
+    }
+}
+
+/// Directions on the field, with the action moving the player there
+const DIRECTIONS: [(IVec2, Action); 4] = [
+    (IVec2::Y, Action::MoveUp),
+    (IVec2::NEG_Y, Action::MoveDown),
+    (IVec2::X, Action::MoveLeft),
+    (IVec2::NEG_X, Action::MoveRight),
+];
+
+/// Actions done with the keyboard, all the connected gamepads and the on-screen buttons
+#[derive(SystemParam)]
+pub struct GameInput<'w> {
+    keyboard: Res<'w, ButtonInput<KeyCode>>,
+    gamepads: Res<'w, Gamepads>,
+    buttons: Res<'w, ButtonInput<GamepadButton>>,
+    axes: Res<'w, Axis<GamepadAxis>>,
+    bindings: Res<'w, Bindings>,
+    virtual_input: Res<'w, VirtualInput>,
+}
+
+impl GameInput<'_> {
+    /// A key or a button of the action is held
+    pub fn pressed(&self, action: Action) -> bool {
+        let binding = self.bindings.get(action);
+        self.virtual_input.pressed(action)
+            || self.keyboard.any_pressed(binding.keys.iter().copied())
+            || binding.buttons.iter().any(|button| {
+                self.gamepads.iter().any(|gamepad| self.buttons.pressed(GamepadButton::new(gamepad, *button)))
+            })
+    }
+
+    pub fn just_pressed(&self, action: Action) -> bool {
+        let binding = self.bindings.get(action);
+        self.virtual_input.just_pressed(action)
+            || self.keyboard.any_just_pressed(binding.keys.iter().copied())
+            || binding.buttons.iter().any(|button| {
+                self.gamepads.iter().any(|gamepad| self.buttons.just_pressed(GamepadButton::new(gamepad, *button)))
+            })
+    }
+
+    /// Directions held with the move actions or the left stick
+    pub fn directions(&self) -> Vec<IVec2> {
+        let mut directions: Vec<IVec2> = DIRECTIONS.iter()
+            .filter(|(_, action)| self.pressed(*action))
+            .map(|(direction, _)| *direction)
+            .collect();
+
+        for gamepad in self.gamepads.iter() {
+            for direction in self.stick_directions(gamepad) {
+                if !directions.contains(&direction) {
+                    directions.push(direction);
+                }
+            }
+        }
+        directions
+    }
+
+    /// Directions pressed during this frame with the move actions
+    pub fn directions_just_pressed(&self) -> Vec<IVec2> {
+        DIRECTIONS.iter()
+            .filter(|(_, action)| self.just_pressed(*action))
+            .map(|(direction, _)| *direction)
+            .collect()
+    }
+
+    /// Directions of the left stick, in eight sectors around the center
+    fn stick_directions(&self, gamepad: Gamepad) -> Vec<IVec2> {
+        let x = self.axes.get(GamepadAxis::new(gamepad, GamepadAxisType::LeftStickX)).unwrap_or(0.0);
+        let y = self.axes.get(GamepadAxis::new(gamepad, GamepadAxisType::LeftStickY)).unwrap_or(0.0);
+        let stick = Vec2::new(x, y);
+        if stick.length() < STICK_DEAD_ZONE {
+            return Vec::new();
+        }
+
+        // Round the angle to the closest of the eight directions
+        let sector = (stick.to_angle() / FRAC_PI_4).round();
+        let snapped = Vec2::from_angle(sector * FRAC_PI_4).round().as_ivec2();
+        // Right on the stick is right on the screen, which is toward -X on the field
+        let mut directions = Vec::new();
+        if snapped.y != 0 {
+            directions.push(IVec2::new(0, snapped.y));
+        }
+        if snapped.x != 0 {
+            directions.push(IVec2::new(-snapped.x, 0));
+        }
+        directions
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bind_key(bindings: &mut Bindings, action: Action, key: KeyCode) -> Vec<Action> {
+        let binding = Binding {
+            keys: vec![key],
+            buttons: bindings.get(action).buttons.clone(),
+        };
+        bindings.set(action, binding)
+    }
+
+    #[test]
+    fn default_bindings_do_not_conflict() {
+        let bindings = Bindings::default();
+        for action in Action::ALL {
+            for other in Action::ALL.into_iter().filter(|other| other.conflicts_with(action)) {
+                let (binding, other_binding) = (bindings.get(action), bindings.get(other));
+                assert!(!binding.keys.iter().any(|key| other_binding.keys.contains(key)), "{action:?} and {other:?}");
+                assert!(!binding.buttons.iter().any(|button| other_binding.buttons.contains(button)), "{action:?} and {other:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn zqsd_takes_the_keys_of_the_other_actions() {
+        let mut bindings = Bindings::default();
+        assert_eq!(bind_key(&mut bindings, Action::MoveUp, KeyCode::KeyZ), vec![Action::Undo]);
+        assert_eq!(bind_key(&mut bindings, Action::MoveLeft, KeyCode::KeyQ), vec![]);
+        assert_eq!(bind_key(&mut bindings, Action::MoveDown, KeyCode::KeyS), vec![Action::Settings]);
+        assert_eq!(bind_key(&mut bindings, Action::MoveRight, KeyCode::KeyD), vec![Action::Daily]);
+
+        assert_eq!(bindings.get(Action::MoveUp).keys, vec![KeyCode::KeyZ]);
+        assert!(bindings.get(Action::Undo).keys.is_empty());
+        assert!(bindings.get(Action::Settings).keys.is_empty());
+        // The pad buttons are kept
+        assert_eq!(bindings.get(Action::Undo).buttons, vec![GamepadButtonType::LeftTrigger2]);
+    }
+
+    #[test]
+    fn actions_of_other_screens_keep_their_keys() {
+        let mut bindings = Bindings::default();
+        // Symmetry is only read in game, Endless only on the landing screen
+        assert_eq!(bind_key(&mut bindings, Action::Symmetry, KeyCode::KeyE), vec![]);
+        assert_eq!(bindings.get(Action::Endless).keys, vec![KeyCode::KeyE]);
+    }
+}
@@ -0,0 +1,346 @@
+/* SPDX-License-Identifier: MIT
+* Copyright (c) 2024 Elieva Pignat, Florian Depraz, Louis Mayencourt
+*/
+
+use std::collections::HashMap;
+
+use bevy::{
+    color::palettes::css::{WHITE, YELLOW},
+    input::keyboard::{Key, KeyboardInput},
+    prelude::*,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::GameState;
+use crate::assist::SymmetryAssist;
+use crate::menu::{CurrentLevel, GameMode, GameScore, LevelTimer};
+use crate::player::Player;
+use crate::progress::level_key;
+use crate::save::SaveGame;
+use crate::world::levels::Level;
+
+/// Points of a run without any mistake, forgotten cell, time or move
+const MAX_POINTS: u32 = 10_000;
+/// Runs kept for every level
+const MAX_ENTRIES: usize = 10;
+/// Runs shown on the score screen
+const SHOWN_ENTRIES: usize = 5;
+/// Longest name a player can type
+const MAX_NAME_LENGTH: usize = 12;
+
+/// Result of a run, as shown in the high-score table
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct LeaderboardEntry {
+    pub name: String,
+    pub mistakes: u32,
+    pub forgotten: u32,
+    /// Time to finish the level, in seconds
+    pub time: f32,
+    pub moves: u32,
+    pub assists: Vec<SymmetryAssist>,
+}
+
+impl LeaderboardEntry {
+    /// Points of the run, higher is better.
+    /// Every mistake and forgotten cell costs 200 points, every second 10 and every move 1,
+    /// then a run helped by a symmetry assist keeps three quarters of its points.
+    pub fn points(&self) -> u32 {
+        let penalty = 200 * (self.mistakes + self.forgotten) + (10.0 * self.time) as u32 + self.moves;
+        let points = MAX_POINTS.saturating_sub(penalty);
+        if self.assisted() {
+            points * 3 / 4
+        } else {
+            points
+        }
+    }
+
+    /// One line of the high-score table
+    pub fn describe(&self) -> String {
+        let assists = if self.assists.is_empty() {
+            "no assist".to_string()
+        } else {
+            self.assists.iter().map(|assist| assist.name()).collect::<Vec<_>>().join(", ")
+        };
+        format!(
+            "{:<12} {:>5} pts   {} mistakes, {} forgotten, {:.1} s, {} moves, {}",
+            self.name, self.points(), self.mistakes, self.forgotten, self.time, self.moves, assists,
+        )
+    }
+
+    /// Whether a symmetry assist helped the run, those runs are ranked apart
+    pub fn assisted(&self) -> bool {
+        !self.assists.is_empty()
+    }
+
+    /// Best runs first, the fastest wins a tie
+    fn rank(&self, other: &Self) -> std::cmp::Ordering {
+        other.points().cmp(&self.points()).then(self.time.total_cmp(&other.time))
+    }
+}
+
+/// Best runs of a level, the runs helped by a symmetry assist in their own table
+#[derive(Default, Debug, Clone, Deserialize, Serialize)]
+struct LevelTables {
+    unassisted: Vec<LeaderboardEntry>,
+    assisted: Vec<LeaderboardEntry>,
+}
+
+impl LevelTables {
+    fn table(&self, assisted: bool) -> &[LeaderboardEntry] {
+        if assisted { &self.assisted } else { &self.unassisted }
+    }
+
+    /// Add a run to its table, only the best ones are kept
+    fn add(&mut self, entry: LeaderboardEntry) {
+        let entries = if entry.assisted() { &mut self.assisted } else { &mut self.unassisted };
+        entries.push(entry);
+        // The sort is stable, the older run stays first on a tie
+        entries.sort_by(|a, b| a.rank(b));
+        entries.truncate(MAX_ENTRIES);
+    }
+}
+
+/// Best runs of every level of the campaign, by level path
+#[derive(Resource, Default, Debug, Clone, Deserialize, Serialize)]
+pub struct Leaderboard(HashMap<String, LevelTables>);
+
+impl Leaderboard {
+    /// Runs of a level with or without an assist, the best first
+    pub fn entries(&self, handle: &Handle<Level>, assisted: bool) -> &[LeaderboardEntry] {
+        self.0.get(&level_key(handle)).map_or(&[], |tables| tables.table(assisted))
+    }
+
+    /// Add a run to the table of a level, only the best ones are kept
+    pub fn add(&mut self, handle: &Handle<Level>, entry: LeaderboardEntry) {
+        self.0.entry(level_key(handle)).or_default().add(entry);
+    }
+}
+
+/// Name written in the next runs, the last one typed
+#[derive(Resource)]
+pub struct PlayerName(pub String);
+
+impl Default for PlayerName {
+    fn default() -> Self {
+        let name = std::env::var("USER")
+            .or_else(|_| std::env::var("USERNAME"))
+            .unwrap_or_else(|_| "Player".to_string());
+        Self(name.chars().take(MAX_NAME_LENGTH).collect())
+    }
+}
+
+/// Run shown on the score screen, added to the table when the screen is left,
+/// once the player had the chance to type a name
+#[derive(Resource, Default)]
+struct PendingEntry(Option<(Handle<Level>, LeaderboardEntry)>);
+
+/// Component to identify the high-score table of the score screen
+#[derive(Component)]
+struct LeaderboardScreen;
+
+pub struct LeaderboardPlugin;
+
+impl Plugin for LeaderboardPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Leaderboard>();
+        app.init_resource::<PlayerName>();
+        app.init_resource::<PendingEntry>();
+        app.add_systems(OnEnter(GameState::Score), open_leaderboard);
+        app.add_systems(OnExit(GameState::Score), close_leaderboard);
+        // Reads the keyboard in every state, to not type the keys pressed during the game
+        app.add_systems(Update, (edit_name, show_leaderboard).chain());
+    }
+}
+
+/// Prepare the entry of the finished run, only the campaign levels have a table
+#[allow(clippy::too_many_arguments)]
+fn open_leaderboard(
+    mut commands: Commands,
+    mut pending: ResMut<PendingEntry>,
+    mode: Res<GameMode>,
+    score: Res<GameScore>,
+    timer: Res<LevelTimer>,
+    current_level: Res<CurrentLevel>,
+    name: Res<PlayerName>,
+    player: Query<&Player>,
+) {
+    if *mode != GameMode::Campaign {
+        return;
+    }
+
+    pending.0 = Some((current_level.handle.clone(), LeaderboardEntry {
+        name: name.0.clone(),
+        mistakes: score.mistakes,
+        forgotten: score.forgotten,
+        time: timer.0.elapsed_secs(),
+        moves: player.single().moves,
+        assists: score.assists.clone(),
+    }));
+
+    let text_style = TextStyle {
+        color: WHITE.into(),
+        font_size: 18.0,
+        ..default()
+    };
+
+    let title = if score.assists.is_empty() { "HIGH SCORES\n" } else { "HIGH SCORES WITH ASSIST\n" };
+    let mut sections = vec![TextSection::new(title, TextStyle { font_size: 28.0, ..text_style.clone() })];
+    sections.extend((0..SHOWN_ENTRIES).map(|_| TextSection::from_style(text_style.clone())));
+    sections.push(TextSection::new("\nType your name, Backspace to erase", text_style.clone()));
+
+    commands.spawn((
+        TextBundle::from_sections(sections).with_style(Style {
+            position_type: PositionType::Absolute,
+            top: Val::Px(10.0),
+            right: Val::Px(10.0),
+            ..default()
+        }),
+        LeaderboardScreen,
+    ));
+}
+
+/// Keep the run in the table, with the name typed
+fn close_leaderboard(
+    mut commands: Commands,
+    screens: Query<Entity, With<LeaderboardScreen>>,
+    mut pending: ResMut<PendingEntry>,
+    mut leaderboard: ResMut<Leaderboard>,
+    mut name: ResMut<PlayerName>,
+    mut save: EventWriter<SaveGame>,
+) {
+    for screen in screens.iter() {
+        commands.entity(screen).despawn_recursive();
+    }
+
+    if let Some((handle, entry)) = pending.0.take() {
+        name.0 = entry.name.clone();
+        leaderboard.add(&handle, entry);
+        save.send_default();
+    }
+}
+
+/// Type the name of the pending entry
+fn edit_name(
+    mut events: EventReader<KeyboardInput>,
+    mut pending: ResMut<PendingEntry>,
+) {
+    for event in events.read() {
+        let Some((_, entry)) = pending.0.as_mut() else {
+            continue;
+        };
+        if !event.state.is_pressed() {
+            continue;
+        }
+        match &event.logical_key {
+            Key::Backspace => {
+                entry.name.pop();
+            }
+            Key::Space if entry.name.chars().count() < MAX_NAME_LENGTH => entry.name.push(' '),
+            Key::Character(text) => {
+                for c in text.chars().filter(|c| !c.is_control()) {
+                    if entry.name.chars().count() < MAX_NAME_LENGTH {
+                        entry.name.push(c);
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Show the best runs of the level with the pending one at its rank, in the table of its kind
+fn show_leaderboard(
+    pending: Res<PendingEntry>,
+    leaderboard: Res<Leaderboard>,
+    mut query: Query<&mut Text, With<LeaderboardScreen>>,
+) {
+    let Some((handle, pending)) = pending.0.as_ref() else {
+        return;
+    };
+    let entries = leaderboard.entries(handle, pending.assisted());
+    let rank = entries.iter().position(|entry| pending.rank(entry).is_lt()).unwrap_or(entries.len());
+
+    for mut text in query.iter_mut() {
+        for idx in 0..SHOWN_ENTRIES {
+            let section = &mut text.sections[idx + 1];
+            let entry = match idx.cmp(&rank) {
+                std::cmp::Ordering::Less => entries.get(idx),
+                std::cmp::Ordering::Equal => Some(pending),
+                std::cmp::Ordering::Greater => entries.get(idx - 1),
+            };
+            section.value = match entry {
+                Some(entry) => format!("{}. {}\n", idx + 1, entry.describe()),
+                None => "\n".to_string(),
+            };
+            section.style.color = if idx == rank { YELLOW.into() } else { WHITE.into() };
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(mistakes: u32, forgotten: u32, time: f32, moves: u32) -> LeaderboardEntry {
+        LeaderboardEntry { name: "Ada".to_string(), mistakes, forgotten, time, moves, assists: Vec::new() }
+    }
+
+    #[test]
+    fn points_count_every_penalty() {
+        assert_eq!(entry(0, 0, 0.0, 0).points(), MAX_POINTS);
+        assert_eq!(entry(2, 1, 12.5, 40).points(), 10_000 - 600 - 125 - 40);
+    }
+
+    #[test]
+    fn points_stop_at_zero() {
+        assert_eq!(entry(40, 20, 0.0, 0).points(), 0);
+        assert_eq!(entry(0, 0, 2000.0, 5).points(), 0);
+    }
+
+    #[test]
+    fn assisted_run_keeps_three_quarters_of_its_points() {
+        let mut run = entry(2, 1, 12.5, 40);
+        run.assists.push(SymmetryAssist::MirrorX);
+        assert!(run.assisted());
+        assert_eq!(run.points(), 9235 * 3 / 4);
+    }
+
+    #[test]
+    fn only_the_best_runs_are_kept_in_order() {
+        let mut leaderboard = Leaderboard::default();
+        let handle = Handle::default();
+        for moves in [50, 10, 120, 30, 70, 0, 90, 20, 110, 60, 100, 40] {
+            leaderboard.add(&handle, entry(0, 0, 10.0, moves));
+        }
+
+        let moves: Vec<u32> = leaderboard.entries(&handle, false).iter().map(|entry| entry.moves).collect();
+        assert_eq!(moves, vec![0, 10, 20, 30, 40, 50, 60, 70, 90, 100]);
+    }
+
+    #[test]
+    fn faster_run_wins_a_tie() {
+        let mut leaderboard = Leaderboard::default();
+        let handle = Handle::default();
+        // Same points, the second run spent its time moving instead of thinking
+        leaderboard.add(&handle, entry(0, 0, 10.0, 100));
+        leaderboard.add(&handle, entry(0, 0, 5.0, 150));
+
+        let times: Vec<f32> = leaderboard.entries(&handle, false).iter().map(|entry| entry.time).collect();
+        assert_eq!(times, vec![5.0, 10.0]);
+    }
+
+    #[test]
+    fn assisted_runs_have_their_own_table() {
+        let mut leaderboard = Leaderboard::default();
+        let handle = Handle::default();
+        let mut assisted = entry(0, 0, 10.0, 0);
+        assisted.assists.push(SymmetryAssist::MirrorY);
+        leaderboard.add(&handle, assisted);
+        leaderboard.add(&handle, entry(5, 5, 60.0, 200));
+
+        assert_eq!(leaderboard.entries(&handle, false).len(), 1);
+        assert_eq!(leaderboard.entries(&handle, false)[0].mistakes, 5);
+        assert_eq!(leaderboard.entries(&handle, true).len(), 1);
+        assert!(leaderboard.entries(&handle, true)[0].assisted());
+    }
+}
@@ -0,0 +1,263 @@
+/* SPDX-License-Identifier: MIT
+* Copyright (c) 2024 Elieva Pignat, Florian Depraz, Louis Mayencourt
+*/
+
+use bevy::{
+    color::palettes::css::{GRAY, WHITE, YELLOW},
+    prelude::*,
+};
+
+use crate::GameState;
+use crate::input::{Action, GameInput, MenuDirections};
+use crate::leaderboard::Leaderboard;
+use crate::menu::{CurrentLevel, GameMode, RestartGame, ReturnState};
+use crate::minimap::{level_preview, stars_text, MinimapColors};
+use crate::progress::Progress;
+use crate::world::levels::{Campaign, Level, LevelList};
+
+/// Number of levels on a row of the screen
+const COLUMNS: usize = 5;
+/// Size of the level previews, in pixels
+const PREVIEW_SIZE: f32 = 96.0;
+/// Best runs shown for the selected level
+const SHOWN_ENTRIES: usize = 3;
+
+/// Level selected in the level select screen
+#[derive(Resource, Default)]
+struct LevelSelectMenu {
+    selected: usize,
+}
+
+/// Component to identify the root of the level select screen
+#[derive(Component)]
+struct LevelSelectScreen;
+
+/// Component to identify the card of a level, by its position in the campaign
+#[derive(Component)]
+struct LevelCard(usize);
+
+/// Component to identify the high scores of the selected level
+#[derive(Component)]
+struct LevelLeaderboard;
+
+pub struct LevelSelectPlugin;
+
+impl Plugin for LevelSelectPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<LevelSelectMenu>();
+        app.add_systems(OnEnter(GameState::LevelSelect), open_level_select);
+        app.add_systems(OnExit(GameState::LevelSelect), close_level_select);
+        app.add_systems(Update, (choose_level, show_selection).chain().run_if(in_state(GameState::LevelSelect)));
+    }
+}
+
+/// List every level of the campaign with its preview and the best score of the player
+#[allow(clippy::too_many_arguments)]
+fn open_level_select(
+    mut commands: Commands,
+    mut menu: ResMut<LevelSelectMenu>,
+    mut images: ResMut<Assets<Image>>,
+    current_level: Res<CurrentLevel>,
+    mode: Res<GameMode>,
+    level_list: Res<LevelList>,
+    campaigns: Res<Assets<Campaign>>,
+    levels: Res<Assets<Level>>,
+    progress: Res<Progress>,
+    colors: Res<MinimapColors>,
+) {
+    let Some(campaign) = campaigns.get(&level_list.campaign) else {
+        return;
+    };
+    // Start on the level being played, the generated levels are not in the list
+    menu.selected = if *mode == GameMode::Campaign { current_level.idx } else { 0 };
+
+    let text_style = TextStyle {
+        color: WHITE.into(),
+        font_size: 18.0,
+        ..default()
+    };
+
+    commands.spawn((
+        NodeBundle {
+            style: Style {
+                width: Val::Percent(100.0),
+                height: Val::Percent(100.0),
+                flex_direction: FlexDirection::Column,
+                justify_content: JustifyContent::Center,
+                align_items: AlignItems::Center,
+                ..default()
+            },
+            background_color: BackgroundColor(Color::srgba(0.0, 0.0, 0.0, 0.8)),
+            ..default()
+        },
+        LevelSelectScreen,
+    ))
+    .with_children(|parent| {
+        parent.spawn(TextBundle::from_section(
+            "LEVEL SELECT\n",
+            TextStyle { font_size: 40.0, ..text_style.clone() },
+        ));
+
+        parent.spawn(NodeBundle {
+            style: Style {
+                width: Val::Px(COLUMNS as f32 * (PREVIEW_SIZE + 32.0)),
+                flex_wrap: FlexWrap::Wrap,
+                justify_content: JustifyContent::Center,
+                ..default()
+            },
+            ..default()
+        })
+        .with_children(|grid| {
+            for (idx, handle) in campaign.levels.iter().enumerate() {
+                let unlocked = progress.is_unlocked(campaign, idx);
+                let record = match progress.record_of(handle) {
+                    _ if !unlocked => "Locked".to_string(),
+                    Some(record) if record.passed => format!(
+                        "{}\n{:.0}% accuracy\n{:.1} s",
+                        stars_text(record.stars),
+                        100.0 * record.accuracy,
+                        record.best_time.unwrap_or_default(),
+                    ),
+                    Some(_) => "Not passed".to_string(),
+                    None => "Not played".to_string(),
+                };
+                let preview = levels.get(handle)
+                    .map(|level| images.add(level_preview(level, &colors)))
+                    .unwrap_or_default();
+
+                grid.spawn((
+                    NodeBundle {
+                        style: Style {
+                            margin: UiRect::all(Val::Px(8.0)),
+                            padding: UiRect::all(Val::Px(4.0)),
+                            border: UiRect::all(Val::Px(4.0)),
+                            flex_direction: FlexDirection::Column,
+                            align_items: AlignItems::Center,
+                            ..default()
+                        },
+                        ..default()
+                    },
+                    LevelCard(idx),
+                ))
+                .with_children(|card| {
+                    card.spawn(ImageBundle {
+                        style: Style {
+                            width: Val::Px(PREVIEW_SIZE),
+                            height: Val::Px(PREVIEW_SIZE),
+                            ..default()
+                        },
+                        image: UiImage::new(preview),
+                        // The locked levels are greyed out
+                        background_color: BackgroundColor(if unlocked { Color::WHITE } else { GRAY.into() }),
+                        ..default()
+                    });
+                    card.spawn(TextBundle::from_section(
+                        format!("Level {}\n{}", idx + 1, record),
+                        text_style.clone(),
+                    ).with_text_justify(JustifyText::Center));
+                });
+            }
+        });
+
+        parent.spawn((TextBundle::from_section("", text_style.clone()), LevelLeaderboard));
+
+        parent.spawn(TextBundle::from_section(
+            "\nArrows: Select   Enter: Play   Escape: Back",
+            text_style.clone(),
+        ));
+    });
+}
+
+fn close_level_select(
+    mut commands: Commands,
+    screens: Query<Entity, With<LevelSelectScreen>>,
+) {
+    for screen in screens.iter() {
+        commands.entity(screen).despawn_recursive();
+    }
+}
+
+/// Move between the levels, and start the selected one if it is unlocked
+#[allow(clippy::too_many_arguments)]
+fn choose_level(
+    input: GameInput,
+    mut menu: ResMut<LevelSelectMenu>,
+    mut next_state: ResMut<NextState<GameState>>,
+    mut current_level: ResMut<CurrentLevel>,
+    mut mode: ResMut<GameMode>,
+    mut restart: EventWriter<RestartGame>,
+    level_list: Res<LevelList>,
+    campaigns: Res<Assets<Campaign>>,
+    progress: Res<Progress>,
+    exit: Res<ReturnState>,
+    mut directions: Local<MenuDirections>,
+) {
+    // Directions on the field: +X is on the left of the screen
+    let pushed = directions.just_pressed(&input);
+    if input.just_pressed(Action::Pause) {
+        next_state.set(exit.0.clone());
+        return;
+    }
+
+    let Some(campaign) = campaigns.get(&level_list.campaign) else {
+        return;
+    };
+    let count = campaign.levels.len();
+    // A campaign emptied while hot reloading has no level to select
+    if count == 0 {
+        return;
+    }
+
+    if pushed.contains(&IVec2::X) {
+        menu.selected = (menu.selected + count - 1) % count;
+    } else if pushed.contains(&IVec2::NEG_X) {
+        menu.selected = (menu.selected + 1) % count;
+    } else if pushed.contains(&IVec2::Y) {
+        menu.selected = menu.selected.saturating_sub(COLUMNS);
+    } else if pushed.contains(&IVec2::NEG_Y) {
+        menu.selected = (menu.selected + COLUMNS).min(count - 1);
+    }
+
+    if input.just_pressed(Action::Finish) && progress.is_unlocked(campaign, menu.selected) {
+        // A level picked during an endless run or a daily challenge ends it
+        *mode = GameMode::Campaign;
+        current_level.select(menu.selected, campaign);
+        restart.send_default();
+        next_state.set(GameState::LandingScreen);
+    }
+}
+
+/// Draw a border around the selected level and list its best runs
+fn show_selection(
+    menu: Res<LevelSelectMenu>,
+    mut cards: Query<(&LevelCard, &mut BorderColor)>,
+    mut texts: Query<&mut Text, With<LevelLeaderboard>>,
+    leaderboard: Res<Leaderboard>,
+    level_list: Res<LevelList>,
+    campaigns: Res<Assets<Campaign>>,
+) {
+    if let Some(handle) = campaigns.get(&level_list.campaign).and_then(|campaign| campaign.levels.get(menu.selected)) {
+        let table = |title: &str, assisted: bool| {
+            let entries = leaderboard.entries(handle, assisted);
+            let lines: String = if entries.is_empty() {
+                "\nNo high score yet".to_string()
+            } else {
+                entries.iter().take(SHOWN_ENTRIES).enumerate()
+                    .map(|(idx, entry)| format!("\n{}. {}", idx + 1, entry.describe()))
+                    .collect()
+            };
+            format!("\n{}{}\n", title, lines)
+        };
+        for mut text in texts.iter_mut() {
+            text.sections[0].value = table("Without assist", false) + &table("With assist", true);
+        }
+    }
+
+    for (card, mut border) in cards.iter_mut() {
+        *border = if card.0 == menu.selected {
+            BorderColor(YELLOW.into())
+        } else {
+            BorderColor(Color::NONE)
+        };
+    }
+}
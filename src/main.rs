@@ -5,43 +5,95 @@
 use bevy::prelude::*;
 use bevy_embedded_assets::{EmbeddedAssetPlugin, PluginMode};
 
+mod assist;
 mod audio;
+mod brush;
 mod camera;
+mod config;
+mod daily;
+mod endless;
+mod history;
+mod input;
+mod leaderboard;
+mod level_select;
 mod menu;
+mod pause;
 mod player;
+mod pointer;
+mod progress;
+mod rng;
+mod save;
+mod settings;
 mod world;
 mod minimap;
 mod sky;
+mod tools;
 
+use assist::AssistPlugin;
 use audio::audio::AudioPlugin;
+use brush::BrushPlugin;
 use camera::GameCameraPlugin;
+use history::HistoryPlugin;
+use leaderboard::LeaderboardPlugin;
+use level_select::LevelSelectPlugin;
+use pause::PausePlugin;
 use player::PlayerPlugin;
+use pointer::PointerPlugin;
+use save::SavePlugin;
 use world::WorldPlugin;
 use minimap::MinimapPlugin;
 use menu::MenuPlugin;
+use rng::GameRng;
+use settings::SettingsPlugin;
 use sky::SkyPlugin;
+use tools::ToolsPlugin;
 
 #[derive(States, Debug, Clone, PartialEq, Eq, Hash)]
 enum GameState {
+    Loading,
     LandingScreen,
     InGame,
     EndGame,
     Score,
     GameOver,
+    Settings,
+    Paused,
+    LevelSelect,
 }
 
 fn main() {
+    // `corn-field import ...` converts an image into a level file instead of starting the game
+    let args: Vec<String> = std::env::args().collect();
+    if args.get(1).map(String::as_str) == Some("import") {
+        if let Err(err) = world::import::run_cli(&args[2..]) {
+            eprintln!("{}", err);
+            std::process::exit(1);
+        }
+        return;
+    }
+
     App::new()
         .add_plugins(EmbeddedAssetPlugin::default())
         .add_plugins(DefaultPlugins)
-        .insert_state(GameState::LandingScreen)
+        .insert_state(GameState::Loading)
+        .init_resource::<GameRng>()
         .add_plugins(SkyPlugin)
         .add_plugins(GameCameraPlugin)
         .add_plugins(WorldPlugin)
         .add_plugins(AudioPlugin)
         .add_plugins(PlayerPlugin)
+        .add_plugins(PointerPlugin)
+        .add_plugins(HistoryPlugin)
+        .add_plugins(BrushPlugin)
+        .add_plugins(ToolsPlugin)
+        .add_plugins(AssistPlugin)
         .add_plugins(MinimapPlugin)
         .add_plugins(MenuPlugin)
+        .add_plugins(SettingsPlugin)
+        .add_plugins(PausePlugin)
+        .add_plugins(LevelSelectPlugin)
+        .add_plugins(LeaderboardPlugin)
+        .add_plugins(SavePlugin)
         
         .run();
 }
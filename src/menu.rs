@@ -2,16 +2,39 @@
 * Copyright (c) 2024 Elieva Pignat, Florian Depraz, Louis Mayencourt
 */
 
-use bevy::prelude::*;
+use bevy::{prelude::*, time::Stopwatch};
+use rand::Rng;
 
 use crate::GameState;
-use crate::world::{Corn, levels::{LEVELS, LEVEL_COUNT}, lights::ShowLights};
+use crate::assist::{Assist, SymmetryAssist};
+use crate::daily::DailyChallenge;
+use crate::endless::EndlessRun;
+use crate::history::History;
+use crate::input::{Action, GameInput};
+use crate::pause::{PauseItem, PauseMenu};
+use crate::progress::Progress;
+use crate::save::SaveGame;
+use crate::rng::GameRng;
+use crate::world::{grid::FieldGrid, levels::{Campaign, Level, LevelList}, lights::ShowLights};
 
 /// Global resource that contains the score of the game
 #[derive(Resource, Default)]
 pub struct GameScore {
     pub forgotten: u32,
     pub mistakes: u32,
+    /// Symmetry assists used during the level
+    pub assists: Vec<SymmetryAssist>,
+    /// Intersection over union of the cut cells and the formation, from 0.0 to 1.0
+    pub accuracy: f32,
+    /// Stars earned with the accuracy, from 0 to 3
+    pub stars: u32,
+}
+
+impl GameScore {
+    /// The crop circle works from the first star
+    pub fn passed(&self) -> bool {
+        self.stars > 0
+    }
 }
 
 /// Component used to mark the mistakes at the end of the game
@@ -25,15 +48,53 @@ struct ComputeScoreEvent;
 #[derive(Event, Default)]
 pub struct RestartGame;
 
-/// Old the previous input to provide a cool down to the enter key
+/// Old the previous input to provide a cool down to the finish action
 #[derive(Resource)]
 struct PreviousKeyboardInput {
-    previous_key: Option<KeyCode>,
+    previous_key: Option<Action>,
 }
 
+/// State the settings and level select screens go back to when they are closed
+#[derive(Resource)]
+pub struct ReturnState(pub GameState);
+
+impl Default for ReturnState {
+    fn default() -> Self {
+        Self(GameState::LandingScreen)
+    }
+}
+
+/// Which levels are played
+#[derive(Resource, Default, Debug, Clone, PartialEq)]
+pub enum GameMode {
+    /// The levels of the campaign, in order
+    #[default]
+    Campaign,
+    /// Generated levels, until a round is failed
+    Endless,
+    /// A single generated level, the same for everybody on a given day
+    Daily(DailyChallenge),
+}
+
+/// Time spent playing the current level, stopped outside of the game and during the pause
+#[derive(Resource, Default)]
+pub struct LevelTimer(pub Stopwatch);
+
 #[derive(Resource, Default)]
 pub struct CurrentLevel {
     pub idx: usize,
+    pub handle: Handle<Level>,
+    /// Level of the campaign unlocked by passing this one
+    pub next: Option<Handle<Level>>,
+}
+
+impl CurrentLevel {
+    /// Select the level at position `idx` in the campaign
+    pub fn select(&mut self, idx: usize, campaign: &Campaign) {
+        self.idx = idx;
+        self.handle = campaign.levels[idx].clone();
+        self.next = campaign.levels.get(idx + 1).cloned();
+    }
 }
 
 pub struct MenuPlugin;
@@ -48,30 +109,114 @@ impl Plugin for MenuPlugin {
         app.add_event::<RestartGame>();
         app.add_systems(Update, restart_all);
         app.insert_resource(CurrentLevel::default());
+        app.init_resource::<GameMode>();
+        app.init_resource::<EndlessRun>();
+        app.init_resource::<LevelTimer>();
+        app.init_resource::<ReturnState>();
+        app.init_resource::<Progress>();
+        app.add_systems(Update, tick_level_timer.run_if(in_state(GameState::InGame)));
     }
 }
 
 fn manage_menu(
     state: Res<State<GameState>>,
-    keyboard_input: Res<ButtonInput<KeyCode>>,
+    input: GameInput,
     mut next_state: ResMut<NextState<GameState>>,
     mut event: EventWriter<ComputeScoreEvent>,
     mut restart: EventWriter<RestartGame>,
     mut old_input: ResMut<PreviousKeyboardInput>,
     mut current_level: ResMut<CurrentLevel>,
-    mut score: ResMut<GameScore>,
+    score: Res<GameScore>,
+    level_list: Res<LevelList>,
+    campaigns: Res<Assets<Campaign>>,
+    mut levels: ResMut<Assets<Level>>,
+    mut mode: ResMut<GameMode>,
+    mut endless: ResMut<EndlessRun>,
+    mut rng: ResMut<GameRng>,
+    mut pause_menu: ResMut<PauseMenu>,
+    mut return_state: ResMut<ReturnState>,
 )
 {
+    let Some(campaign) = campaigns.get(&level_list.campaign) else {
+        return;
+    };
+    let level_count = campaign.levels.len();
+    // A campaign emptied while hot reloading has no level to go to
+    if level_count == 0 {
+        return;
+    }
+
     match state.get() {
+        GameState::Loading | GameState::Settings | GameState::LevelSelect => {}
         GameState::LandingScreen => {
-            if keyboard_input.any_pressed([KeyCode::ArrowDown, KeyCode::ArrowUp, KeyCode::ArrowLeft, KeyCode::ArrowRight]) {
+            if !input.directions().is_empty() {
                 next_state.set(GameState::InGame);
             }
+            if input.just_pressed(Action::Endless) && *mode == GameMode::Campaign {
+                *mode = GameMode::Endless;
+                *endless = EndlessRun::new(rng.gen());
+                current_level.handle = levels.add(endless.level());
+                restart.send_default();
+            }
+            if input.just_pressed(Action::Settings) {
+                return_state.0 = GameState::LandingScreen;
+                next_state.set(GameState::Settings);
+            }
+            if input.just_pressed(Action::LevelSelect) {
+                return_state.0 = GameState::LandingScreen;
+                next_state.set(GameState::LevelSelect);
+            }
+            if input.just_pressed(Action::Daily) && *mode == GameMode::Campaign {
+                let daily = DailyChallenge::today();
+                info!("Daily challenge of {}", daily.date);
+                current_level.handle = levels.add(daily.level());
+                *mode = GameMode::Daily(daily);
+                restart.send_default();
+            }
         }
         GameState::InGame => {
-            if keyboard_input.pressed(KeyCode::Enter) && old_input.previous_key.is_none(){
-                next_state.set(GameState::EndGame);
-                old_input.previous_key = Some(KeyCode::Enter);
+            // The end of the mission is confirmed in the pause menu
+            if input.pressed(Action::Finish) && old_input.previous_key.is_none(){
+                pause_menu.open(PauseItem::Finish);
+                next_state.set(GameState::Paused);
+                old_input.previous_key = Some(Action::Finish);
+            }
+            if input.just_pressed(Action::Pause) {
+                pause_menu.open(PauseItem::Resume);
+                next_state.set(GameState::Paused);
+            }
+        }
+        GameState::Paused => {
+            if input.just_pressed(Action::Pause) {
+                next_state.set(GameState::InGame);
+            } else if input.pressed(Action::Finish) && old_input.previous_key.is_none(){
+                old_input.previous_key = Some(Action::Finish);
+                match pause_menu.selected() {
+                    PauseItem::Resume => next_state.set(GameState::InGame),
+                    PauseItem::Finish => next_state.set(GameState::EndGame),
+                    PauseItem::Restart => {
+                        restart.send_default();
+                        next_state.set(GameState::LandingScreen);
+                    }
+                    PauseItem::LevelSelect => {
+                        return_state.0 = GameState::Paused;
+                        next_state.set(GameState::LevelSelect);
+                    }
+                    PauseItem::Settings => {
+                        return_state.0 = GameState::Paused;
+                        next_state.set(GameState::Settings);
+                    }
+                    PauseItem::Quit => {
+                        // Generated levels are left for the campaign
+                        if *mode != GameMode::Campaign {
+                            *mode = GameMode::Campaign;
+                            let idx = current_level.idx;
+                            current_level.select(idx, campaign);
+                        }
+                        restart.send_default();
+                        next_state.set(GameState::LandingScreen);
+                    }
+                }
             }
         }
         GameState::EndGame => {
@@ -79,19 +224,46 @@ fn manage_menu(
             next_state.set(GameState::Score);
         }
         GameState::Score => {
-            let mut passed_level = false;
-            if score.mistakes <= LEVELS[current_level.idx].mistake_level && score.forgotten <= LEVELS[current_level.idx].forgotten_level {
-                passed_level = true;
-            }
-            if current_level.idx == LEVEL_COUNT - 1 && passed_level {
-                next_state.set(GameState::GameOver);
+            let Some(level) = levels.get(&current_level.handle) else {
+                return;
+            };
+            let passed_level = score.passed();
+            if let GameMode::Daily(_) = *mode {
+                // There is a single daily level, go back to the campaign afterwards
+                if input.pressed(Action::Finish) && old_input.previous_key.is_none(){
+                    old_input.previous_key = Some(Action::Finish);
+                    *mode = GameMode::Campaign;
+                    let idx = current_level.idx;
+                    current_level.select(idx, campaign);
+                    restart.send_default();
+                    next_state.set(GameState::LandingScreen);
+                }
+            } else if *mode == GameMode::Endless {
+                // Endless rounds continue until one is failed
+                if input.pressed(Action::Finish) && old_input.previous_key.is_none(){
+                    old_input.previous_key = Some(Action::Finish);
+                    if endless.finish_round(level, &score) {
+                        current_level.handle = levels.add(endless.level());
+                        restart.send_default();
+                        next_state.set(GameState::LandingScreen);
+                    } else {
+                        next_state.set(GameState::GameOver);
+                    }
+                }
+            } else if current_level.idx == level_count - 1 && passed_level {
+                // Stay on the score until confirmed, the player may be typing a name for the high scores
+                if input.pressed(Action::Finish) && old_input.previous_key.is_none(){
+                    old_input.previous_key = Some(Action::Finish);
+                    next_state.set(GameState::GameOver);
+                }
             } else {
-                if keyboard_input.pressed(KeyCode::Enter) && old_input.previous_key.is_none(){
+                if input.pressed(Action::Finish) && old_input.previous_key.is_none(){
                     restart.send_default();
-                    old_input.previous_key = Some(KeyCode::Enter);
-                    if current_level.idx < LEVEL_COUNT-1 {
+                    old_input.previous_key = Some(Action::Finish);
+                    if current_level.idx < level_count-1 {
                         if passed_level {
-                            current_level.idx += 1;
+                            let next_idx = current_level.idx + 1;
+                            current_level.select(next_idx, campaign);
                         }
 
                     } 
@@ -101,84 +273,110 @@ fn manage_menu(
             }
         }
         GameState::GameOver => {
-            if keyboard_input.pressed(KeyCode::Enter) && old_input.previous_key.is_none(){
+            if input.pressed(Action::Finish) && old_input.previous_key.is_none(){
                 next_state.set(GameState::LandingScreen);
-                old_input.previous_key = Some(KeyCode::Enter);
+                old_input.previous_key = Some(Action::Finish);
+                // An endless run goes back to the campaign level it was started from,
+                // only a completed campaign starts over
+                let idx = if *mode == GameMode::Endless { current_level.idx } else { 0 };
+                *mode = GameMode::Campaign;
+                current_level.select(idx, campaign);
                 restart.send_default();
-                current_level.idx = 0;
             }
         }
     }
 
-    if !keyboard_input.pressed(KeyCode::Enter) {
+    if !input.pressed(Action::Finish) {
         old_input.previous_key = None;
     }
 }
 
 fn compute_score(
-    event: EventReader<ComputeScoreEvent>,
-    corn: Query<(&Transform), With<Corn>>,
+    mut event: EventReader<ComputeScoreEvent>,
+    field: Res<FieldGrid>,
     mut commands: Commands,
     mut meshes: ResMut<Assets<Mesh>>,
     mut materials: ResMut<Assets<StandardMaterial>>,
     mut score: ResMut<GameScore>,
     current_level: Res<CurrentLevel>,
+    levels: Res<Assets<Level>>,
     mut lights: EventWriter<ShowLights>,
+    history: Res<History>,
+    assist: Res<Assist>,
+    mode: Res<GameMode>,
+    mut progress: ResMut<Progress>,
+    timer: Res<LevelTimer>,
+    mut save: EventWriter<SaveGame>,
 ) {
     if !event.is_empty() {
-        // Use a static table with enough space for all grid
-        let mut field_map = [[0; 33 as usize]; 33 as usize];
-        for corn_position in corn.iter() {
-            field_map[corn_position.translation.x as usize][corn_position.translation.z as usize] = 1;
+        event.clear();
+        let Some(level) = levels.get(&current_level.handle) else {
+            return;
+        };
+
+        let diff = field.diff(&level.pattern);
+        // Every undo counts as a mistake when the undo penalty is enabled
+        score.mistakes = diff.mistakes.len() as u32 + history.undos * history.undo_penalty;
+        score.forgotten = diff.forgotten.len() as u32;
+        score.assists = assist.used.clone();
+        score.accuracy = level.accuracy(score.mistakes, score.forgotten);
+        score.stars = level.stars_for(score.accuracy);
+
+        for cell in diff.forgotten {
+            commands.spawn((PbrBundle {
+                mesh: meshes.add(Cuboid::new(1.0, 0.2, 1.0)),
+                material: materials.add(Color::srgba(0.0, 0.0, 1.0, 0.4)),
+                transform: Transform::from_xyz(cell.x as f32, 1.1, cell.y as f32),
+                ..default()
+                },
+                ScoreMarker
+            ));
         }
 
-        score.mistakes = 0;
-        score.forgotten = 0;
-        for (y, line) in LEVELS[current_level.idx].data.lines().enumerate() {
-            debug!("line {} is {:?}", y, line);
-            for (x, char) in line.chars().enumerate() {
-                if char == '0' {
-                    if field_map[x][y] == 1 {
-                        score.forgotten += 1;
-                        commands.spawn((PbrBundle {
-                            mesh: meshes.add(Cuboid::new(1.0, 0.2, 1.0)),
-                            material: materials.add(Color::srgba(0.0, 0.0, 1.0, 0.4)),
-                            transform: Transform::from_xyz(x as f32, 1.1, y as f32),
-                            ..default()
-                            },
-                            ScoreMarker
-                        ));
-                    }
-                } else {
-                    if field_map[x][y] == 0 {
-                        score.mistakes += 1;
-                        commands.spawn((PbrBundle {
-                            mesh: meshes.add(Cuboid::new(1.0, 0.2, 1.0)),
-                            material: materials.add(Color::srgba(1.0, 0.0, 0.0, 0.4)),
-                            transform: Transform::from_xyz(x as f32, 1.1, y as f32),
-                            ..default()
-                            },
-                            ScoreMarker
-                        ));
-                    }
-                }
-            }
+        for cell in diff.mistakes {
+            commands.spawn((PbrBundle {
+                mesh: meshes.add(Cuboid::new(1.0, 0.2, 1.0)),
+                material: materials.add(Color::srgba(1.0, 0.0, 0.0, 0.4)),
+                transform: Transform::from_xyz(cell.x as f32, 1.1, cell.y as f32),
+                ..default()
+                },
+                ScoreMarker
+            ));
         }
 
         if score.mistakes == 0 && score.forgotten == 0 {
             lights.send_default();
         }
 
-        info!("Score: {} forgotten, {} mistakes", score.forgotten, score.mistakes);
+        // Only the campaign levels are unlocked one after the other
+        if *mode == GameMode::Campaign {
+            let time = timer.0.elapsed_secs();
+            progress.record(&current_level.handle, current_level.next.as_ref(), &score, time);
+            save.send_default();
+        }
+
+        info!(
+            "Score: {} forgotten, {} mistakes, {:.0}% accuracy, {} stars",
+            score.forgotten, score.mistakes, 100.0 * score.accuracy, score.stars,
+        );
     }
 }
 
+fn tick_level_timer(
+    time: Res<Time>,
+    mut timer: ResMut<LevelTimer>,
+) {
+    timer.0.tick(time.delta());
+}
+
 fn restart_all(
     event: EventReader<RestartGame>,
     mut commands: Commands,
     markers: Query<Entity, With<ScoreMarker>>,
+    mut timer: ResMut<LevelTimer>,
 ) {
     if !event.is_empty() {
+        timer.0.reset();
         for marker in markers.iter() {
             commands.entity(marker).despawn();
         }
@@ -1,12 +1,24 @@
 use bevy::{
-    color::palettes::css::{ANTIQUE_WHITE, GRAY, WHITE, YELLOW},
+    color::palettes::css::{ANTIQUE_WHITE, GRAY, LIME, RED, WHITE, YELLOW},
     prelude::*,
+    render::{
+        render_asset::RenderAssetUsages,
+        render_resource::{Extent3d, TextureDimension, TextureFormat},
+        texture::ImageSampler,
+    },
 };
 
-use crate::{menu::CurrentLevel, world::levels::LEVEL_COUNT};
-use crate::menu::GameScore;
-use crate::world::levels::LEVELS;
+use crate::brush::SelectedBrush;
+use crate::endless::EndlessRun;
+use crate::input::{Action, Bindings};
+use crate::menu::{CurrentLevel, GameMode, GameScore, RestartGame};
+use crate::player::{Player, Seeds};
+use crate::tools::{DrawTool, ToolKind};
+use crate::world::{CellChanged, WorldReset};
+use crate::world::grid::{CellState, FieldGrid};
+use crate::world::levels::{Campaign, Level, LevelList};
 use crate::GameState;
+use crate::assist::Assist;
 
 pub struct MinimapPlugin;
 
@@ -22,42 +34,148 @@ const LABEL_SCORE: i32 = 1;
 const LABEL_LEVEL: i32 = 2;
 const LABEL_INDIC: i32 = 3;
 const LABEL_OVER: i32 = 4;
+const LABEL_HELP: i32 = 5;
+
+/// Size of a cell of the field on the minimap image, in pixels
+const MINIMAP_CELL_PIXELS: usize = 4;
 
 // If you add minimap component you cannot add minimap2 component
 #[derive(Component)]
 pub struct Minimap;
 
+/// Colours used to draw the level and the progress of the player on the minimap
+#[derive(Resource, Clone, Debug)]
+pub struct MinimapColors {
+    pub standing: Color,
+    /// Cells of the pattern which are still standing
+    pub pattern: Color,
+    /// Cells of the pattern which are cut
+    pub cut: Color,
+    /// Cells cut outside of the pattern, drawn with `cut` when `None`
+    pub mistake: Option<Color>,
+    pub obstacle: Color,
+    pub player: Color,
+}
+
+impl Default for MinimapColors {
+    fn default() -> Self {
+        Self {
+            standing: Color::BLACK,
+            pattern: Color::WHITE,
+            cut: LIME.into(),
+            mistake: Some(RED.into()),
+            obstacle: GRAY.into(),
+            player: YELLOW.into(),
+        }
+    }
+}
+
+/// Image of the minimap, drawn from the pattern of the current level and the field
+#[derive(Resource)]
+struct MinimapImage(Handle<Image>);
+
+/// Position of the player as drawn on the minimap
+#[derive(Default)]
+struct DrawnPlayer {
+    cell: Option<IVec2>,
+    heading: IVec2,
+}
+
 impl Plugin for MinimapPlugin {
     fn build(&self, app: &mut App) {
+        app.init_resource::<MinimapColors>();
         app.add_systems(Startup, setup);
         app.add_systems(Update, update_text);
-        app.add_systems(Update, update_minimap);
+        // The whole minimap is redrawn after a restart, once the field is reset
+        app.add_systems(Update, (draw_minimap, update_minimap).chain().after(WorldReset));
     }
 }
 
+#[allow(clippy::too_many_arguments)]
 fn update_text(
     mut query: Query<(&mut Visibility, &TextLabel, &mut Text)>,
     state: Res<State<GameState>>,
     score: ResMut<GameScore>,
     current_level: ResMut<CurrentLevel>,
+    levels: Res<Assets<Level>>,
+    level_list: Option<Res<LevelList>>,
+    campaigns: Res<Assets<Campaign>>,
+    mode: Res<GameMode>,
+    endless: Res<EndlessRun>,
+    seeds: Res<Seeds>,
+    brush: Res<SelectedBrush>,
+    tool: Res<DrawTool>,
+    assist: Res<Assist>,
+    bindings: Res<Bindings>,
 ) {
-    let level_size = LEVELS[current_level.idx].grid_size as f32;
+    let Some(level) = levels.get(&current_level.handle) else {
+        return;
+    };
+    let Some(campaign) = level_list.and_then(|list| campaigns.get(&list.campaign)) else {
+        return;
+    };
 
     for (mut visible, label, mut text) in query.iter_mut() {
         if label.label == LABEL_LEVEL {
-            text.sections[1].value = (current_level.idx + 1).to_string().clone();
-            text.sections[3].value = level_size.to_string().clone();
-            text.sections[5].value = level_size.to_string().clone();
+            if let GameMode::Daily(daily) = &*mode {
+                text.sections[0].value = "Daily ".to_string();
+                text.sections[1].value = daily.date.clone();
+            } else if *mode == GameMode::Endless {
+                text.sections[0].value = "Round ".to_string();
+                text.sections[1].value = (endless.round + 1).to_string();
+            } else {
+                text.sections[0].value = "Level ".to_string();
+                text.sections[1].value = (current_level.idx + 1).to_string().clone();
+            }
+            text.sections[3].value = level.pattern.width().to_string();
+            text.sections[5].value = level.pattern.height().to_string();
+            text.sections[7].value = seeds.left.to_string();
+            text.sections[9].value = match tool.kind {
+                ToolKind::Brush => format!("{} brush", brush.0.name()),
+                kind => kind.name().to_string(),
+            };
+            text.sections[11].value = assist.mode.name();
         }
         if label.label == LABEL_SCORE {
             *visible = Visibility::Hidden;
         }
+        if label.label == LABEL_HELP {
+            text.sections[0].value = format!(
+                "Move: {}/{}/{}/{}   Cut: {}   Brush: {}   Line/Circle: {}   Symmetry: {}   Replant: {}   Undo/Redo: {}/{}   Finish: {}   Pause: {}",
+                bindings.key_name(Action::MoveUp),
+                bindings.key_name(Action::MoveDown),
+                bindings.key_name(Action::MoveLeft),
+                bindings.key_name(Action::MoveRight),
+                bindings.key_name(Action::Cut),
+                bindings.key_name(Action::Brush),
+                bindings.key_name(Action::Tool),
+                bindings.key_name(Action::Symmetry),
+                bindings.key_name(Action::Replant),
+                bindings.key_name(Action::Undo),
+                bindings.key_name(Action::Redo),
+                bindings.key_name(Action::Finish),
+                bindings.key_name(Action::Pause),
+            );
+        }
         if label.label == LABEL_OVER {
             *visible = Visibility::Hidden;
         }
 
-        if *state.get() != GameState::LandingScreen {
-            if label.label == LABEL_INTRO {
+        if label.label == LABEL_INTRO {
+            if *state.get() == GameState::LandingScreen {
+                *visible = Visibility::Visible;
+                // Other modes can only be started from the campaign
+                text.sections[3].value = match *mode {
+                    GameMode::Campaign => format!(
+                        "\nPress {} for endless mode, {} for the daily challenge, {} to pick a level, {} for the settings",
+                        bindings.key_name(Action::Endless),
+                        bindings.key_name(Action::Daily),
+                        bindings.key_name(Action::LevelSelect),
+                        bindings.key_name(Action::Settings),
+                    ),
+                    _ => String::new(),
+                };
+            } else {
                 *visible = Visibility::Hidden;
             }
         }
@@ -70,9 +188,13 @@ fn update_text(
 
         if *state.get() == GameState::InGame {
             if label.label == LABEL_INDIC {
-                if current_level.idx == 0 {
+                if let GameMode::Daily(daily) = &*mode {
+                    text.sections[0].value = format!(" > Daily challenge of {}", daily.date);
+                } else if *mode == GameMode::Endless {
+                    text.sections[0].value = format!(" > Endless mode, {} points so far", endless.points);
+                } else if current_level.idx == 0 {
                     text.sections[0].value = "> Let start simple.".to_string();
-                } else if current_level.idx < (LEVEL_COUNT -1) {
+                } else if current_level.idx < (campaign.levels.len() -1) {
                     text.sections[0].value = " > You are ready to scale up!".to_string();
                 } else {
                     text.sections[0].value = " > Last one to go!".to_string();
@@ -85,12 +207,16 @@ fn update_text(
                 *visible = Visibility::Visible;
                 text.sections[2].value = score.mistakes.to_string().clone();
                 text.sections[4].value = score.forgotten.to_string().clone();
+                text.sections[6].value = format!("{:.0}%", 100.0 * score.accuracy);
+                text.sections[8].value = stars_text(score.stars);
             }
             if label.label == LABEL_INDIC {
                 if score.mistakes == 0 && score.forgotten == 0 {
                     text.sections[0].value = " > Excellent!".to_string();
-                } else if score.mistakes <= LEVELS[current_level.idx].mistake_level && score.forgotten <= LEVELS[current_level.idx].forgotten_level {
+                } else if score.passed() {
                     text.sections[0].value = " > Not perfect but we will make do...".to_string();   
+                } else if *mode == GameMode::Endless {
+                    text.sections[0].value = " > The circle is not working, the run is over".to_string();
                 } else {
                     text.sections[0].value = " > The circle is not working, try again".to_string();
                 }
@@ -100,7 +226,11 @@ fn update_text(
 
         if *state.get() == GameState::GameOver {
             if label.label == LABEL_INDIC {
-                text.sections[0].value = " > Well done! The mission is completed.".to_string();   
+                if *mode == GameMode::Endless {
+                    text.sections[0].value = format!(" > The run is over after {} rounds with {} points.", endless.round + 1, endless.points);
+                } else {
+                    text.sections[0].value = " > Well done! The mission is completed.".to_string();   
+                }
             }
             if label.label == LABEL_OVER  {
                 *visible = Visibility::Visible;
@@ -109,18 +239,160 @@ fn update_text(
     }
 }
 
+/// Draw the pattern of the current level with the cells cut by the player.
+/// The whole image is only drawn when the level or the colours change, the cut
+/// cells and the player are then updated from the events.
+#[allow(clippy::too_many_arguments)]
+fn draw_minimap(
+    current_level: Res<CurrentLevel>,
+    levels: Res<Assets<Level>>,
+    mut level_events: EventReader<AssetEvent<Level>>,
+    mut restart: EventReader<RestartGame>,
+    mut changed: EventReader<CellChanged>,
+    field: Res<FieldGrid>,
+    player: Query<&Player>,
+    colors: Res<MinimapColors>,
+    minimap: Res<MinimapImage>,
+    mut images: ResMut<Assets<Image>>,
+    mut drawn_player: Local<DrawnPlayer>,
+) {
+    let Some(level) = levels.get(&current_level.handle) else {
+        return;
+    };
+    let player = player.single();
+
+    let level_modified = level_events.read().any(|event| {
+        event.is_loaded_with_dependencies(&current_level.handle) || event.is_modified(&current_level.handle)
+    });
+    let restarted = !restart.is_empty();
+    restart.clear();
+
+    if current_level.is_changed() || colors.is_changed() || level_modified || restarted {
+        changed.clear();
+        images.insert(&minimap.0, minimap_image(&level.pattern, &field, &colors));
+        drawn_player.cell = None;
+    } else if changed.is_empty() && drawn_player.cell == Some(player.cell) && drawn_player.heading == player.heading {
+        return;
+    }
+
+    let Some(image) = images.get_mut(&minimap.0) else {
+        return;
+    };
+    // The field can only be drawn over the image of the same level
+    if image.width() as usize != level.pattern.width() * MINIMAP_CELL_PIXELS
+        || image.height() as usize != level.pattern.height() * MINIMAP_CELL_PIXELS
+    {
+        return;
+    }
+
+    for event in changed.read() {
+        fill_cell(image, event.cell, cell_color(&level.pattern, &field, event.cell, &colors));
+    }
+    if let Some(cell) = drawn_player.cell {
+        fill_cell(image, cell, cell_color(&level.pattern, &field, cell, &colors));
+    }
+    if field.contains(player.cell) {
+        draw_player(image, player.cell, player.heading, colors.player);
+        drawn_player.cell = Some(player.cell);
+        drawn_player.heading = player.heading;
+    }
+}
+
+/// Image of a level, in the same orientation as the pattern of the level files
+fn minimap_image(pattern: &FieldGrid, field: &FieldGrid, colors: &MinimapColors) -> Image {
+    let width = pattern.width() * MINIMAP_CELL_PIXELS;
+    let height = pattern.height() * MINIMAP_CELL_PIXELS;
+
+    let mut image = Image::new_fill(
+        Extent3d {
+            width: width as u32,
+            height: height as u32,
+            depth_or_array_layers: 1,
+        },
+        TextureDimension::D2,
+        &colors.standing.to_srgba().to_u8_array(),
+        TextureFormat::Rgba8UnormSrgb,
+        RenderAssetUsages::RENDER_WORLD | RenderAssetUsages::MAIN_WORLD,
+    );
+    // Keep the cells sharp when the image is scaled up
+    image.sampler = ImageSampler::nearest();
+
+    for (cell, _) in pattern.iter() {
+        let color = cell_color(pattern, field, cell, colors);
+        if color != colors.standing {
+            fill_cell(&mut image, cell, color);
+        }
+    }
+
+    image
+}
+
+/// Stars earned in a level, the missing ones as dots
+pub fn stars_text(stars: u32) -> String {
+    (0..3).map(|idx| if idx < stars { '*' } else { '.' }).collect()
+}
+
+/// Image of the pattern of a level, for the level previews
+pub fn level_preview(level: &Level, colors: &MinimapColors) -> Image {
+    minimap_image(&level.pattern, &FieldGrid::start_of(&level.pattern), colors)
+}
+
+/// Colour of a cell, from the state wanted by the pattern and the actual state of the field
+fn cell_color(pattern: &FieldGrid, field: &FieldGrid, cell: IVec2, colors: &MinimapColors) -> Color {
+    match (pattern.get(cell), field.get(cell)) {
+        (Some(CellState::Obstacle), _) => colors.obstacle,
+        (Some(CellState::Cut), Some(CellState::Cut)) => colors.cut,
+        (Some(CellState::Cut), _) => colors.pattern,
+        (_, Some(CellState::Cut)) => colors.mistake.unwrap_or(colors.cut),
+        _ => colors.standing,
+    }
+}
+
+/// Paint the pixels of a cell of the minimap for which `mask(x, y)` is true
+fn paint_cell(image: &mut Image, cell: IVec2, color: Color, mask: impl Fn(usize, usize) -> bool) {
+    let width = image.width() as usize;
+    let color = color.to_srgba().to_u8_array();
+    for y in 0..MINIMAP_CELL_PIXELS {
+        let line = (cell.y as usize * MINIMAP_CELL_PIXELS + y) * width;
+        for x in 0..MINIMAP_CELL_PIXELS {
+            if mask(x, y) {
+                let pixel = (line + cell.x as usize * MINIMAP_CELL_PIXELS + x) * 4;
+                image.data[pixel..pixel + 4].copy_from_slice(&color);
+            }
+        }
+    }
+}
+
+/// Paint all the pixels of a cell of the minimap
+fn fill_cell(image: &mut Image, cell: IVec2, color: Color) {
+    paint_cell(image, cell, color, |_, _| true);
+}
+
+/// Mark the player with a dot in the middle of its cell and a line on the side it is facing
+fn draw_player(image: &mut Image, cell: IVec2, heading: IVec2, color: Color) {
+    let middle = (MINIMAP_CELL_PIXELS as f32 - 1.0) / 2.0;
+    let heading = heading.as_vec2().normalize_or_zero();
+    paint_cell(image, cell, color, |x, y| {
+        let offset = Vec2::new(x as f32 - middle, y as f32 - middle);
+        let forward = offset.dot(heading);
+        let side = offset.perp_dot(heading).abs();
+        (offset.abs().max_element() < 1.0) || (forward > middle - 0.5 && side < 1.0)
+    });
+}
+
 fn update_minimap(
     asset_server: Res<AssetServer>,
     mut query: Query<&mut UiImage, With<Minimap>>,
-    current_level: ResMut<CurrentLevel>,
+    minimap: Res<MinimapImage>,
     state: Res<State<GameState>>
 
 ) {
     let mut image = query.single_mut();
     
     if *state.get() != GameState::GameOver {
-        let texture_handle_map = asset_server.load(LEVELS[current_level.idx].image);
-        *image = UiImage::new(texture_handle_map);
+        if image.texture != minimap.0 {
+            *image = UiImage::new(minimap.0.clone());
+        }
     } else {
         let texture_handle_over = asset_server.load("game_over.png");
         *image = UiImage::new(texture_handle_over);
@@ -130,13 +402,13 @@ fn update_minimap(
 
 fn setup(
     mut commands: Commands,
-    asset_server: Res<AssetServer>,
-    current_level: ResMut<CurrentLevel>,
-    
+    mut images: ResMut<Assets<Image>>,
 ) {
     let text_style = TextStyle::default();
 
-    let texture_handle = asset_server.load(LEVELS[current_level.idx].image);
+    // Replaced by draw_minimap once the level is loaded
+    let minimap = images.add(Image::default());
+    commands.insert_resource(MinimapImage(minimap.clone()));
 
     // root node
     commands
@@ -163,7 +435,7 @@ fn setup(
 
                         ..default()
                     },
-                    image: UiImage::new(texture_handle),
+                    image: UiImage::new(minimap),
                     background_color: BackgroundColor(ANTIQUE_WHITE.into()),
                     ..default()
                 },
@@ -195,8 +467,8 @@ fn setup(
             ));
             parent.spawn((
                 TextBundle::from_section(
-                    // Accepts a `String` or any type that converts into a `String`, such as `&str`
-                    "Arrows: Move           Spacebar: Cut the plants        Enter: Finish the mission",
+                    // Filled by update_text with the keys of the bindings
+                    "",
                     TextStyle {
                         color: WHITE.into(),
                         font_size: 24.0,
@@ -211,6 +483,9 @@ fn setup(
                     left: Val::Px(200.0),
                     ..default()
                 }),
+                TextLabel {
+                    label: LABEL_HELP,
+                },
             ));
             parent.spawn((TextBundle::from_sections([
                 TextSection::new("Press ".to_string(), text_style.clone()),
@@ -221,7 +496,8 @@ fn setup(
                         ..text_style.clone()
                     },
                 ),
-                TextSection::new(" to start".to_string(), text_style),
+                TextSection::new(" to start".to_string(), text_style.clone()),
+                TextSection::from_style(text_style),
             ])
 
             .with_text_justify(JustifyText::Center)
@@ -273,6 +549,32 @@ fn setup(
                         ..default()
                     },
                 ),
+                TextSection::new(
+                    "\nAccuracy: ".to_string(),
+                    TextStyle {
+                        color: WHITE.into(),
+                        ..default()
+                    },
+                ),
+                TextSection::from_style(
+                    TextStyle {
+                        color: WHITE.into(),
+                        ..default()
+                    },
+                ),
+                TextSection::new(
+                    "\nStars: ".to_string(),
+                    TextStyle {
+                        color: WHITE.into(),
+                        ..default()
+                    },
+                ),
+                TextSection::from_style(
+                    TextStyle {
+                        color: YELLOW.into(),
+                        ..default()
+                    },
+                ),
             ])
 
             .with_text_justify(JustifyText::Left)
@@ -331,6 +633,45 @@ fn setup(
                     ..default()
                 },
             ),
+            TextSection::new(
+                "\nSeeds: ".to_string(),
+                TextStyle {
+                    color: WHITE.into(),
+                    ..default()
+                },
+            ),
+            TextSection::from_style(
+                TextStyle {
+                    color: WHITE.into(),
+                    ..default()
+                },
+            ),
+            TextSection::new(
+                "\nTool: ".to_string(),
+                TextStyle {
+                    color: WHITE.into(),
+                    ..default()
+                },
+            ),
+            TextSection::from_style(
+                TextStyle {
+                    color: WHITE.into(),
+                    ..default()
+                },
+            ),
+            TextSection::new(
+                "\nSymmetry: ".to_string(),
+                TextStyle {
+                    color: WHITE.into(),
+                    ..default()
+                },
+            ),
+            TextSection::from_style(
+                TextStyle {
+                    color: WHITE.into(),
+                    ..default()
+                },
+            ),
         ])
 
         .with_text_justify(JustifyText::Left)
@@ -0,0 +1,161 @@
+/* SPDX-License-Identifier: MIT
+* Copyright (c) 2024 Elieva Pignat, Florian Depraz, Louis Mayencourt
+*/
+
+use bevy::{
+    color::palettes::css::{WHITE, YELLOW},
+    prelude::*,
+};
+
+use crate::GameState;
+use crate::input::{GameInput, MenuDirections};
+
+/// Entries of the pause menu
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PauseItem {
+    Resume,
+    Finish,
+    Restart,
+    LevelSelect,
+    Settings,
+    Quit,
+}
+
+impl PauseItem {
+    const ALL: [PauseItem; 6] = [
+        PauseItem::Resume,
+        PauseItem::Finish,
+        PauseItem::Restart,
+        PauseItem::LevelSelect,
+        PauseItem::Settings,
+        PauseItem::Quit,
+    ];
+
+    fn name(&self) -> &'static str {
+        match self {
+            Self::Resume => "Resume",
+            Self::Finish => "Finish the mission",
+            Self::Restart => "Restart the level",
+            Self::LevelSelect => "Level select",
+            Self::Settings => "Settings",
+            Self::Quit => "Quit to title",
+        }
+    }
+}
+
+/// Entry selected in the pause menu
+#[derive(Resource, Default)]
+pub struct PauseMenu {
+    selected: usize,
+}
+
+impl PauseMenu {
+    /// Open the menu on an entry, the finish action opens it on `Finish` to confirm the end of the mission
+    pub fn open(&mut self, item: PauseItem) {
+        self.selected = PauseItem::ALL.iter().position(|other| *other == item).unwrap_or(0);
+    }
+
+    pub fn selected(&self) -> PauseItem {
+        PauseItem::ALL[self.selected]
+    }
+}
+
+/// Component to identify the root of the pause menu
+#[derive(Component)]
+struct PauseScreen;
+
+/// Component to identify the text listing the entries
+#[derive(Component)]
+struct PauseList;
+
+pub struct PausePlugin;
+
+impl Plugin for PausePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<PauseMenu>();
+        app.add_systems(OnEnter(GameState::Paused), open_pause);
+        app.add_systems(OnExit(GameState::Paused), close_pause);
+        app.add_systems(Update, (navigate_pause, show_pause).chain().run_if(in_state(GameState::Paused)));
+    }
+}
+
+/// Freeze the game time, the moves and the effects stop where they are
+fn open_pause(
+    mut commands: Commands,
+    mut time: ResMut<Time<Virtual>>,
+) {
+    time.pause();
+
+    let text_style = TextStyle {
+        color: WHITE.into(),
+        font_size: 28.0,
+        ..default()
+    };
+
+    commands.spawn((
+        NodeBundle {
+            style: Style {
+                width: Val::Percent(100.0),
+                height: Val::Percent(100.0),
+                justify_content: JustifyContent::Center,
+                align_items: AlignItems::Center,
+                ..default()
+            },
+            background_color: BackgroundColor(Color::srgba(0.0, 0.0, 0.0, 0.6)),
+            ..default()
+        },
+        PauseScreen,
+    ))
+    .with_children(|parent| {
+        let mut sections = vec![TextSection::new("PAUSE\n\n", TextStyle { font_size: 48.0, ..text_style.clone() })];
+        sections.extend(PauseItem::ALL.iter().map(|_| TextSection::from_style(text_style.clone())));
+        parent.spawn((
+            TextBundle::from_sections(sections).with_text_justify(JustifyText::Center),
+            PauseList,
+        ));
+    });
+}
+
+fn close_pause(
+    mut commands: Commands,
+    screens: Query<Entity, With<PauseScreen>>,
+    mut time: ResMut<Time<Virtual>>,
+) {
+    for screen in screens.iter() {
+        commands.entity(screen).despawn_recursive();
+    }
+    time.unpause();
+}
+
+/// The selected entry is chosen with the finish action, in `manage_menu`
+fn navigate_pause(
+    input: GameInput,
+    mut menu: ResMut<PauseMenu>,
+    mut directions: Local<MenuDirections>,
+) {
+    let count = PauseItem::ALL.len();
+    let pushed = directions.just_pressed(&input);
+    if pushed.contains(&IVec2::Y) {
+        menu.selected = (menu.selected + count - 1) % count;
+    } else if pushed.contains(&IVec2::NEG_Y) {
+        menu.selected = (menu.selected + 1) % count;
+    }
+}
+
+fn show_pause(
+    menu: Res<PauseMenu>,
+    mut list: Query<&mut Text, With<PauseList>>,
+) {
+    for mut text in list.iter_mut() {
+        for (idx, item) in PauseItem::ALL.iter().enumerate() {
+            let section = &mut text.sections[idx + 1];
+            if idx == menu.selected {
+                section.value = format!("> {} <\n", item.name());
+                section.style.color = YELLOW.into();
+            } else {
+                section.value = format!("{}\n", item.name());
+                section.style.color = WHITE.into();
+            }
+        }
+    }
+}
@@ -1,24 +1,91 @@
+use std::collections::{HashMap, VecDeque};
 use std::f32::consts::PI;
 
 use bevy::prelude::*;
 
 use crate::GameState;
-use crate::world::{Corn, levels::LEVELS};
-use crate::menu::{RestartGame, CurrentLevel};
+use crate::assist::Assist;
+use crate::brush::SelectedBrush;
+use crate::history::{FieldAction, History};
+use crate::input::{Action, GameInput};
+use crate::world::CellChanged;
+use crate::world::grid::{CellState, FieldGrid};
+use crate::menu::{CurrentLevel, RestartGame};
+use crate::tools::{DrawTool, ToolKind};
+use crate::world::levels::Level;
+
+/// Time between two replanted cells, in seconds
+const REPLANT_COOLDOWN: f32 = 1.0;
+/// Time to move from one cell to the next, in seconds
+const MOVE_DURATION: f32 = 0.18;
+/// How fast the player turns toward its heading, higher is faster
+const TURN_SPEED: f32 = 20.0;
 
 #[derive(Default)]
 pub struct PlayerPlugin;
 
 #[derive(Component)]
 pub struct Player {
+    /// Progress of the move to the current cell
     move_delay: Timer,
+    /// Position of the model when the move to the current cell started
+    move_from: Vec3,
+    /// Direction pressed during a move, played once the move is done
+    queued: Option<IVec2>,
+    /// Cells to go through to reach a tapped cell, the next one is last
+    path: Vec<IVec2>,
+    /// Logical position of the player on the field
+    pub cell: IVec2,
+    /// Direction the player is facing, one cell long
+    pub heading: IVec2,
+    /// Number of cells walked since the start of the level
+    pub moves: u32,
+}
+
+/// Walk the player to a cell, going around the obstacles
+#[derive(Event)]
+pub struct MoveTo {
+    pub cell: IVec2,
+}
+
+/// Movement options chosen by the player
+#[derive(Resource)]
+pub struct MovementSettings {
+    /// Move in eight directions when two arrows are pressed, if the level allows it
+    pub diagonals: bool,
+}
+
+impl Default for MovementSettings {
+    fn default() -> Self {
+        Self { diagonals: true }
+    }
+}
+
+/// Corn the player can still replant in the current level
+#[derive(Resource)]
+pub struct Seeds {
+    pub left: u32,
+    cooldown: Timer,
+}
+
+impl Default for Seeds {
+    fn default() -> Self {
+        Self {
+            left: 0,
+            cooldown: Timer::from_seconds(REPLANT_COOLDOWN, TimerMode::Once),
+        }
+    }
 }
 
 impl Plugin for PlayerPlugin {
     fn build(&self, app: &mut App) {
         app.add_systems(Startup, setup_player);
-        app.add_systems(Update, move_player);
+        app.add_event::<MoveTo>();
+        app.add_systems(Update, (plan_path, move_player, animate_player).chain());
         app.add_systems(Update, cut_corn);
+        app.add_systems(Update, replant_corn);
+        app.init_resource::<Seeds>();
+        app.init_resource::<MovementSettings>();
         app.add_systems(Update, reset_player);
     }
 }
@@ -41,7 +108,13 @@ fn setup_player(
             ..default()
         },
         Player {
-            move_delay: Timer::from_seconds(0.18, TimerMode::Once),
+            move_delay: Timer::from_seconds(MOVE_DURATION, TimerMode::Once),
+            move_from: Vec3::ZERO,
+            queued: None,
+            path: Vec::new(),
+            cell: IVec2::ZERO,
+            heading: IVec2::Y,
+            moves: 0,
         },
     )
     ).with_children(
@@ -61,96 +134,303 @@ fn setup_player(
 
 }
 
+/// Direction held by the player, zero when none is.
+/// Without diagonals, the direction pressed last wins, then the one the player is already following.
+fn input_direction(input: &GameInput, heading: IVec2, diagonals: bool) -> IVec2 {
+    let pressed = input.directions();
+
+    if diagonals {
+        // Opposite arrows cancel each other
+        return pressed.iter().sum::<IVec2>().clamp(IVec2::NEG_ONE, IVec2::ONE);
+    }
+
+    input.directions_just_pressed().first().copied()
+        .or(pressed.iter().find(|direction| **direction == heading).copied())
+        .or(pressed.first().copied())
+        .unwrap_or(IVec2::ZERO)
+}
+
+/// The player can go to the next cell in `direction`, diagonals cannot squeeze between two obstacles
+fn can_move(field: &FieldGrid, cell: IVec2, direction: IVec2) -> bool {
+    field.is_walkable(cell + direction)
+        && (direction.x == 0 || direction.y == 0
+            || field.is_walkable(cell + IVec2::new(direction.x, 0))
+            || field.is_walkable(cell + IVec2::new(0, direction.y)))
+}
+
+/// Rotation of the model facing `heading`
+fn heading_rotation(heading: IVec2) -> Quat {
+    Quat::from_rotation_y((-heading.x as f32).atan2(-heading.y as f32))
+}
+
+/// Shortest path from `from` to `to`, without the starting cell and reversed, the next cell is last.
+/// Empty when `to` cannot be reached.
+fn find_path(field: &FieldGrid, from: IVec2, to: IVec2, diagonals: bool) -> Vec<IVec2> {
+    let mut directions = vec![IVec2::X, IVec2::NEG_X, IVec2::Y, IVec2::NEG_Y];
+    if diagonals {
+        directions.extend([IVec2::ONE, IVec2::NEG_ONE, IVec2::new(1, -1), IVec2::new(-1, 1)]);
+    }
+
+    // Breadth first search, every cell remembers where it was reached from
+    let mut previous = HashMap::from([(from, from)]);
+    let mut queue = VecDeque::from([from]);
+    while let Some(cell) = queue.pop_front() {
+        if cell == to {
+            break;
+        }
+        for direction in directions.iter() {
+            let next = cell + *direction;
+            if can_move(field, cell, *direction) && !previous.contains_key(&next) {
+                previous.insert(next, cell);
+                queue.push_back(next);
+            }
+        }
+    }
+
+    let mut path = Vec::new();
+    if !previous.contains_key(&to) {
+        return path;
+    }
+    let mut cell = to;
+    while cell != from {
+        path.push(cell);
+        cell = previous[&cell];
+    }
+    path
+}
+
+/// Diagonal moves need to be allowed by both the player and the level
+fn diagonals_allowed(settings: &MovementSettings, current_level: &CurrentLevel, levels: &Assets<Level>) -> bool {
+    settings.diagonals && levels.get(&current_level.handle).is_none_or(|level| level.diagonals)
+}
+
+fn plan_path(
+    mut events: EventReader<MoveTo>,
+    mut query: Query<&mut Player>,
+    field: Res<FieldGrid>,
+    settings: Res<MovementSettings>,
+    current_level: Res<CurrentLevel>,
+    levels: Res<Assets<Level>>,
+) {
+    if let Some(event) = events.read().last() {
+        let mut player = query.single_mut();
+        let diagonals = diagonals_allowed(&settings, &current_level, &levels);
+        player.path = find_path(&field, player.cell, event.cell, diagonals);
+    }
+}
+
+/// Move the player on the grid. The logical cell changes at once, the model
+/// follows it with `animate_player`.
+#[allow(clippy::too_many_arguments)]
 fn move_player(
-    keyboard_input: Res<ButtonInput<KeyCode>>,
-    mut query: Query<(&mut Transform, &mut Player)>,
+    input: GameInput,
+    mut query: Query<(&Transform, &mut Player)>,
     time: Res<Time>,
     state: Res<State<GameState>>,
+    field: Res<FieldGrid>,
+    settings: Res<MovementSettings>,
     current_level: Res<CurrentLevel>,
+    levels: Res<Assets<Level>>,
 ) {
-    let level_size = LEVELS[current_level.idx].grid_size as f32;
-    let (mut tt, mut player) = query.single_mut();
-    // Access the x, y, z coordinates
-    let mut x = tt.translation.x;
-    let mut z = tt.translation.z;
-
-    let mut rotation: f32 = 0.0;
-    if *state.get() == GameState::InGame {
-        if player.move_delay.tick(time.delta()).finished() {
-            let mut moved = false;
-
-            if keyboard_input.pressed(KeyCode::ArrowUp) {
-                if z < level_size - 1.0 {
-                    z += 1.0;
-                }
-                rotation = PI;
-                moved = true;
-            }
+    let (tt, mut player) = query.single_mut();
+    if *state.get() != GameState::InGame {
+        return;
+    }
 
-            if keyboard_input.pressed(KeyCode::ArrowDown) {
-                if z > 0.0 {
-                    z -= 1.0;
-                }
-                rotation = 0.0;
-                moved = true;
-            }
+    let diagonals = diagonals_allowed(&settings, &current_level, &levels);
+    let direction = input_direction(&input, player.heading, diagonals);
+    if !player.move_delay.tick(time.delta()).finished() {
+        // Remember the arrows pressed during the move, to not lose quick taps
+        if !input.directions_just_pressed().is_empty() && direction != IVec2::ZERO {
+            player.queued = Some(direction);
+        }
+        return;
+    }
 
-            if keyboard_input.pressed(KeyCode::ArrowLeft) {
-                if x < level_size - 1.0 {
-                    x += 1.0;
-                }
-                rotation = -PI / 2.;
-                moved = true;
-            }
+    // Every move takes the same time, diagonal or not
+    let direction = match player.queued.take() {
+        Some(queued) if direction == IVec2::ZERO => queued,
+        _ => direction,
+    };
+    // The arrows take over from the path to a tapped cell
+    let direction = if direction != IVec2::ZERO {
+        player.path.clear();
+        direction
+    } else {
+        match player.path.pop() {
+            Some(next) => next - player.cell,
+            None => return,
+        }
+    };
 
-            if keyboard_input.pressed(KeyCode::ArrowRight) {
-                if x > 0.0 {
-                    x -= 1.0;
-                }
-                rotation = PI/2.0;
-                moved = true;
-            }
+    player.heading = direction;
+    if can_move(&field, player.cell, direction) {
+        player.cell += direction;
+        player.moves += 1;
+    }
 
-            if moved {
-                player.move_delay.reset();
+    player.move_delay.reset();
+    player.move_from = tt.translation;
+}
 
-                tt.translation.x = x;
-                tt.translation.z = z;
-                tt.rotation = Quat::from_rotation_y(rotation);
-            }
-        }
-    }
+/// Slide the model toward the cell of the player and turn it toward its heading
+fn animate_player(
+    mut query: Query<(&mut Transform, &Player)>,
+    time: Res<Time>,
+) {
+    let (mut tt, player) = query.single_mut();
+
+    // Ease in and out, the move starts and ends slowly
+    let t = player.move_delay.fraction();
+    let t = t * t * (3.0 - 2.0 * t);
+    let target = Vec3::new(player.cell.x as f32, player.move_from.y, player.cell.y as f32);
+    tt.translation = player.move_from.lerp(target, t);
+
+    let turn = 1.0 - (-TURN_SPEED * time.delta_seconds()).exp();
+    tt.rotation = tt.rotation.slerp(heading_rotation(player.heading), turn);
 }
 
+#[allow(clippy::too_many_arguments)]
 fn cut_corn(
-    mut commands: Commands,
-    keyboard_input: Res<ButtonInput<KeyCode>>,
-    player: Query<&Transform, With<Player>>,
-    corn: Query<(&Transform, Entity), With<Corn>>,
-    state: Res<State<GameState>>
+    input: GameInput,
+    player: Query<&Player>,
+    mut field: ResMut<FieldGrid>,
+    state: Res<State<GameState>>,
+    mut changed: EventWriter<CellChanged>,
+    mut history: ResMut<History>,
+    brush: Res<SelectedBrush>,
+    tool: Res<DrawTool>,
+    mut assist: ResMut<Assist>,
 ) {
     let player = player.single();
-    if *state.get() == GameState::InGame {
-        if keyboard_input.pressed(KeyCode::Space) {
-            // If space bar pressed, remove the corn at the position of the player
-            for (corn_position, corn) in corn.iter() {
-                if player.translation.x == corn_position.translation.x &&
-                    player.translation.z == corn_position.translation.z {
-                        commands.entity(corn).despawn();
-                }
-            }   
+    // The line and circle tools cut on their own
+    if *state.get() == GameState::InGame && tool.kind == ToolKind::Brush {
+        if input.pressed(Action::Cut) {
+            // If space bar pressed, cut the corn under the brush
+            let cut = assist.cut(brush.0.cells(player.cell, player.heading), &mut history, &mut field);
+            changed.send_batch(cut.into_iter().map(|cell| CellChanged { cell }));
         }
     }
 }
 
+/// Regrow the corn of the cell of the player, to repair a mistake.
+/// The new corn is spawned by the world when it syncs with the field.
+#[allow(clippy::too_many_arguments)]
+fn replant_corn(
+    input: GameInput,
+    player: Query<&Player>,
+    mut field: ResMut<FieldGrid>,
+    state: Res<State<GameState>>,
+    time: Res<Time>,
+    mut seeds: ResMut<Seeds>,
+    mut changed: EventWriter<CellChanged>,
+    mut history: ResMut<History>,
+) {
+    if *state.get() != GameState::InGame {
+        return;
+    }
+    seeds.cooldown.tick(time.delta());
+    let player = player.single();
+    if input.just_pressed(Action::Replant)
+        && seeds.left > 0
+        && seeds.cooldown.finished()
+        && field.get(player.cell) == Some(CellState::Cut)
+    {
+        // Undoing the replant does not give the seed back
+        history.apply(&mut field, FieldAction::replant(player.cell));
+        changed.send(CellChanged { cell: player.cell });
+        seeds.left -= 1;
+        seeds.cooldown.reset();
+    }
+}
+
 fn reset_player(
     event: EventReader<RestartGame>,
-    mut query: Query<&mut Transform, With<Player>>,
+    mut query: Query<(&mut Transform, &mut Player)>,
+    mut seeds: ResMut<Seeds>,
+    current_level: Res<CurrentLevel>,
+    levels: Res<Assets<Level>>,
 ) {
     if !event.is_empty() {
-        let mut transform = query.single_mut();
+        if let Some(level) = levels.get(&current_level.handle) {
+            seeds.left = level.seeds;
+        }
+        let (mut transform, mut player) = query.single_mut();
+        player.cell = IVec2::ZERO;
+        player.heading = IVec2::Y;
+        player.moves = 0;
         transform.translation.x = 0.0;
         transform.translation.z = 0.0;
         transform.rotation= Quat::from_rotation_y(PI);
+        // Stop any move in progress, the player is directly on its cell
+        player.move_from = transform.translation;
+        player.queued = None;
+        player.path.clear();
+        let duration = player.move_delay.duration();
+        player.move_delay.set_elapsed(duration);
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn path_is_reversed_without_the_start() {
+        let field = FieldGrid::new(4, 1);
+        assert_eq!(
+            find_path(&field, IVec2::new(0, 0), IVec2::new(3, 0), false),
+            vec![IVec2::new(3, 0), IVec2::new(2, 0), IVec2::new(1, 0)],
+        );
+        assert!(find_path(&field, IVec2::new(1, 0), IVec2::new(1, 0), false).is_empty());
+    }
+
+    #[test]
+    fn path_goes_around_obstacles() {
+        let mut field = FieldGrid::new(3, 3);
+        field.set(IVec2::new(1, 0), CellState::Obstacle);
+        field.set(IVec2::new(1, 1), CellState::Obstacle);
+
+        let path = find_path(&field, IVec2::new(0, 0), IVec2::new(2, 0), false);
+        assert_eq!(path.len(), 6);
+        assert_eq!(path.first(), Some(&IVec2::new(2, 0)));
+        assert!(!path.contains(&IVec2::new(1, 0)));
+    }
+
+    #[test]
+    fn path_is_empty_when_unreachable() {
+        let mut field = FieldGrid::new(3, 1);
+        field.set(IVec2::new(1, 0), CellState::Obstacle);
+        assert!(find_path(&field, IVec2::new(0, 0), IVec2::new(2, 0), true).is_empty());
+        assert!(find_path(&field, IVec2::new(0, 0), IVec2::new(5, 0), true).is_empty());
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn diagonals_shorten_the_path() {
+        let field = FieldGrid::new(4, 4);
+        assert_eq!(find_path(&field, IVec2::ZERO, IVec2::new(3, 3), false).len(), 6);
+        assert_eq!(
+            find_path(&field, IVec2::ZERO, IVec2::new(3, 3), true),
+            vec![IVec2::new(3, 3), IVec2::new(2, 2), IVec2::new(1, 1)],
+        );
+    }
+
+    #[test]
+    fn diagonal_cannot_squeeze_between_obstacles() {
+        let mut field = FieldGrid::new(2, 2);
+        field.set(IVec2::new(1, 0), CellState::Obstacle);
+        assert!(can_move(&field, IVec2::ZERO, IVec2::ONE));
+
+        field.set(IVec2::new(0, 1), CellState::Obstacle);
+        assert!(!can_move(&field, IVec2::ZERO, IVec2::ONE));
+        assert!(find_path(&field, IVec2::ZERO, IVec2::ONE, true).is_empty());
+    }
+
+    #[test]
+    fn cannot_move_on_an_obstacle_or_outside() {
+        let mut field = FieldGrid::new(2, 2);
+        field.set(IVec2::new(1, 1), CellState::Obstacle);
+        assert!(!can_move(&field, IVec2::ZERO, IVec2::ONE));
+        assert!(!can_move(&field, IVec2::ZERO, IVec2::NEG_X));
+        assert!(can_move(&field, IVec2::ZERO, IVec2::X));
+    }
+}
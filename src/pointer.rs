@@ -0,0 +1,270 @@
+/* SPDX-License-Identifier: MIT
+* Copyright (c) 2024 Elieva Pignat, Florian Depraz, Louis Mayencourt
+*/
+
+use bevy::{
+    color::palettes::css::WHITE,
+    prelude::*,
+    ui::UiSystem,
+    window::PrimaryWindow,
+};
+
+use crate::GameState;
+use crate::assist::Assist;
+use crate::brush::SelectedBrush;
+use crate::camera::MainCamera;
+use crate::history::History;
+use crate::input::{Action, VirtualInput};
+use crate::player::MoveTo;
+use crate::tools::{line_cells, DrawTool, ToolKind};
+use crate::world::CellChanged;
+use crate::world::grid::FieldGrid;
+
+/// Size of the on-screen buttons, in pixels
+const BUTTON_SIZE: f32 = 64.0;
+
+/// On-screen button doing an action, for touch screens
+#[derive(Component)]
+struct TouchButton(Action);
+
+/// Component to identify the root of the on-screen buttons
+#[derive(Component)]
+struct TouchControls;
+
+/// Mouse button or finger held on the field
+#[derive(Resource, Default)]
+struct PointerDrag {
+    /// Cell under the pointer when it was pressed, `None` when the pointer is not held on the field
+    start: Option<IVec2>,
+    /// Cell under the pointer during the last frame
+    last: IVec2,
+    /// The pointer left its first cell, the cells under it are cut
+    dragging: bool,
+}
+
+pub struct PointerPlugin;
+
+impl Plugin for PointerPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<VirtualInput>();
+        app.init_resource::<PointerDrag>();
+        app.add_systems(Startup, setup_touch_controls);
+        // The actions are pressed before the game systems of `Update` read them
+        app.add_systems(
+            PreUpdate,
+            (begin_virtual_input, press_touch_buttons, drag_pointer, show_touch_controls)
+                .chain()
+                .after(UiSystem::Focus),
+        );
+    }
+}
+
+fn begin_virtual_input(mut virtual_input: ResMut<VirtualInput>) {
+    virtual_input.begin_frame();
+}
+
+fn press_touch_buttons(
+    buttons: Query<(&Interaction, &TouchButton)>,
+    mut virtual_input: ResMut<VirtualInput>,
+) {
+    for (interaction, button) in buttons.iter() {
+        if *interaction == Interaction::Pressed {
+            virtual_input.press(button.0);
+        }
+    }
+}
+
+/// Position of the mouse or of the first finger, in the window
+fn pointer_position(window: &Window, mouse: &ButtonInput<MouseButton>, touches: &Touches) -> Option<Vec2> {
+    if let Some(position) = touches.first_pressed_position() {
+        Some(position)
+    } else if mouse.pressed(MouseButton::Left) {
+        window.cursor_position()
+    } else {
+        None
+    }
+}
+
+/// Cell of the field under a position of the window
+fn cell_under(position: Vec2, camera: &Camera, camera_transform: &GlobalTransform, field: &FieldGrid) -> Option<IVec2> {
+    let ray = camera.viewport_to_world(camera_transform, position)?;
+    let distance = ray.intersect_plane(Vec3::ZERO, InfinitePlane3d::new(Vec3::Y))?;
+    let point = ray.get_point(distance);
+    let cell = IVec2::new(point.x.round() as i32, point.z.round() as i32);
+    field.contains(cell).then_some(cell)
+}
+
+/// A tap walks the player to the cell, a drag cuts under the pointer and the player follows it.
+/// The brush cuts along the drag, the line and circle tools from the pressed cell to the released one.
+/// The path of the player to the pointer does not cut anything.
+#[allow(clippy::too_many_arguments)]
+fn drag_pointer(
+    state: Res<State<GameState>>,
+    windows: Query<&Window, With<PrimaryWindow>>,
+    cameras: Query<(&Camera, &GlobalTransform), With<MainCamera>>,
+    mouse: Res<ButtonInput<MouseButton>>,
+    touches: Res<Touches>,
+    buttons: Query<&Interaction, With<TouchButton>>,
+    mut field: ResMut<FieldGrid>,
+    mut drag: ResMut<PointerDrag>,
+    mut move_to: EventWriter<MoveTo>,
+    mut history: ResMut<History>,
+    mut changed: EventWriter<CellChanged>,
+    mut assist: ResMut<Assist>,
+    mut tool: ResMut<DrawTool>,
+    brush: Res<SelectedBrush>,
+) {
+    let (Ok(window), Ok((camera, camera_transform))) = (windows.get_single(), cameras.get_single()) else {
+        return;
+    };
+
+    let position = pointer_position(window, &mouse, &touches);
+    let cell = position.and_then(|position| cell_under(position, camera, camera_transform, &field));
+
+    let Some(start) = drag.start else {
+        // A press on the on-screen buttons is not a tap on the field
+        let on_buttons = buttons.iter().any(|interaction| *interaction != Interaction::None);
+        let just_pressed = mouse.just_pressed(MouseButton::Left) || touches.any_just_pressed();
+        if *state.get() == GameState::InGame && just_pressed && !on_buttons {
+            if let Some(cell) = cell {
+                if tool.kind != ToolKind::Brush {
+                    // Shows the preview of the shape while dragging
+                    tool.start = Some(cell);
+                }
+                *drag = PointerDrag {
+                    start: Some(cell),
+                    last: cell,
+                    dragging: false,
+                };
+            }
+        }
+        return;
+    };
+
+    if position.is_none() || *state.get() != GameState::InGame {
+        if *state.get() == GameState::InGame {
+            if !drag.dragging {
+                // Released without leaving the first cell, this is a tap
+                move_to.send(MoveTo { cell: start });
+            } else if tool.kind != ToolKind::Brush {
+                // Like marking the pressed cell, then the released one, with the cut action
+                let cut = assist.cut(tool.kind.cells(start, drag.last), &mut history, &mut field);
+                changed.send_batch(cut.into_iter().map(|cell| CellChanged { cell }));
+            }
+            if tool.kind != ToolKind::Brush {
+                tool.start = None;
+            }
+        }
+        *drag = PointerDrag::default();
+        return;
+    }
+
+    if let Some(cell) = cell {
+        if cell != drag.last {
+            if tool.kind == ToolKind::Brush {
+                // The pointer can skip cells when it moves fast, the brush goes along the line
+                // between the two, facing the way the pointer moves like the player would
+                let cells = line_cells(drag.last, cell).windows(2).flat_map(|step| {
+                    let heading = step[1] - step[0];
+                    [brush.0.cells(step[0], heading), brush.0.cells(step[1], heading)].concat()
+                }).collect();
+                let cut = assist.cut(cells, &mut history, &mut field);
+                changed.send_batch(cut.into_iter().map(|cell| CellChanged { cell }));
+            }
+
+            drag.dragging = true;
+            drag.last = cell;
+            move_to.send(MoveTo { cell });
+        }
+    }
+}
+
+/// The buttons are only shown once the screen has been touched
+fn show_touch_controls(
+    touches: Res<Touches>,
+    state: Res<State<GameState>>,
+    mut controls: Query<&mut Visibility, With<TouchControls>>,
+    mut touched: Local<bool>,
+) {
+    *touched |= touches.any_just_pressed();
+    let visible = *touched && !matches!(state.get(), GameState::Loading | GameState::Settings);
+    for mut visibility in controls.iter_mut() {
+        *visibility = if visible { Visibility::Visible } else { Visibility::Hidden };
+    }
+}
+
+fn spawn_button(parent: &mut ChildBuilder, label: &str, action: Action, left: f32, bottom: f32) {
+    parent.spawn((
+        ButtonBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                left: Val::Px(left),
+                bottom: Val::Px(bottom),
+                width: Val::Px(BUTTON_SIZE),
+                height: Val::Px(BUTTON_SIZE),
+                justify_content: JustifyContent::Center,
+                align_items: AlignItems::Center,
+                ..default()
+            },
+            background_color: BackgroundColor(Color::srgba(1.0, 1.0, 1.0, 0.2)),
+            ..default()
+        },
+        TouchButton(action),
+    ))
+    .with_children(|button| {
+        button.spawn(TextBundle::from_section(
+            label,
+            TextStyle {
+                color: WHITE.into(),
+                font_size: 20.0,
+                ..default()
+            },
+        ));
+    });
+}
+
+/// D-pad at the bottom left of the screen, cut, undo and finish at the bottom right
+fn setup_touch_controls(mut commands: Commands) {
+    commands.spawn((
+        NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                left: Val::Px(20.0),
+                bottom: Val::Px(20.0),
+                width: Val::Px(BUTTON_SIZE * 3.0),
+                height: Val::Px(BUTTON_SIZE * 3.0),
+                ..default()
+            },
+            visibility: Visibility::Hidden,
+            ..default()
+        },
+        TouchControls,
+    ))
+    .with_children(|parent| {
+        spawn_button(parent, "Up", Action::MoveUp, BUTTON_SIZE, BUTTON_SIZE * 2.0);
+        spawn_button(parent, "Left", Action::MoveLeft, 0.0, BUTTON_SIZE);
+        spawn_button(parent, "Right", Action::MoveRight, BUTTON_SIZE * 2.0, BUTTON_SIZE);
+        spawn_button(parent, "Down", Action::MoveDown, BUTTON_SIZE, 0.0);
+    });
+
+    commands.spawn((
+        NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                right: Val::Px(20.0),
+                bottom: Val::Px(20.0),
+                width: Val::Px(BUTTON_SIZE * 2.0 + 10.0),
+                height: Val::Px(BUTTON_SIZE * 2.0 + 10.0),
+                ..default()
+            },
+            visibility: Visibility::Hidden,
+            ..default()
+        },
+        TouchControls,
+    ))
+    .with_children(|parent| {
+        spawn_button(parent, "Cut", Action::Cut, 0.0, 0.0);
+        spawn_button(parent, "Undo", Action::Undo, BUTTON_SIZE + 10.0, 0.0);
+        spawn_button(parent, "Done", Action::Finish, BUTTON_SIZE + 10.0, BUTTON_SIZE + 10.0);
+    });
+}
@@ -0,0 +1,149 @@
+/* SPDX-License-Identifier: MIT
+* Copyright (c) 2024 Elieva Pignat, Florian Depraz, Louis Mayencourt
+*/
+
+use std::cmp::Reverse;
+use std::collections::{HashMap, HashSet};
+
+use bevy::asset::AssetPath;
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::menu::GameScore;
+use crate::world::levels::{Campaign, Level};
+
+/// Best result of the player on a level of the campaign
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize, Serialize)]
+pub struct LevelRecord {
+    pub passed: bool,
+    pub mistakes: u32,
+    pub forgotten: u32,
+    #[serde(default)]
+    pub accuracy: f32,
+    #[serde(default)]
+    pub stars: u32,
+    /// Fastest time to pass the level, in seconds
+    #[serde(default)]
+    pub best_time: Option<f32>,
+}
+
+/// Progress of the player in the campaign
+#[derive(Resource, Default, Debug, Clone, Deserialize, Serialize)]
+pub struct Progress {
+    /// Levels unlocked by passing the one before them, the first level of the campaign always is
+    pub unlocked: HashSet<String>,
+    /// Best results, by level path
+    pub records: HashMap<String, LevelRecord>,
+}
+
+impl Progress {
+    /// Whether the level at `idx` in the campaign can be played
+    pub fn is_unlocked(&self, campaign: &Campaign, idx: usize) -> bool {
+        idx == 0 || campaign.levels.get(idx).is_some_and(|handle| self.unlocked.contains(&level_key(handle)))
+    }
+
+    pub fn record_of(&self, handle: &Handle<Level>) -> Option<&LevelRecord> {
+        self.records.get(&level_key(handle))
+    }
+
+    /// Keep the score of the level if it is the best one,
+    /// the `next` level is unlocked when it is passed
+    pub fn record(&mut self, handle: &Handle<Level>, next: Option<&Handle<Level>>, score: &GameScore, time: f32) {
+        self.record_key(level_key(handle), next.map(level_key), score, time);
+    }
+
+    fn record_key(&mut self, key: String, next: Option<String>, score: &GameScore, time: f32) {
+        let passed = score.passed();
+        let new = LevelRecord {
+            passed,
+            mistakes: score.mistakes,
+            forgotten: score.forgotten,
+            accuracy: score.accuracy,
+            stars: score.stars,
+            best_time: if passed { Some(time) } else { None },
+        };
+        let record = self.records.entry(key).or_insert(new);
+        // A pass is always better than a fail, then the more stars and the fewer errors the better
+        let rank = |record: &LevelRecord| (!record.passed, Reverse(record.stars), record.mistakes + record.forgotten);
+        // The best time is kept on its own, it can come from another run than the best score
+        let best_time = match (record.best_time, new.best_time) {
+            (Some(old), Some(new)) => Some(old.min(new)),
+            (old, new) => old.or(new),
+        };
+        if rank(&new) < rank(record) {
+            *record = new;
+        }
+        record.best_time = best_time;
+
+        if let Some(next) = next.filter(|_| passed) {
+            self.unlocked.insert(next);
+        }
+    }
+}
+
+/// Name of a level in the progress, the path of its file.
+/// The asset source is left out, it differs between the embedded and the hot reloaded assets.
+pub fn level_key(handle: &Handle<Level>) -> String {
+    handle.path().map(path_key).unwrap_or_default()
+}
+
+fn path_key(path: &AssetPath) -> String {
+    path.path().to_string_lossy().into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn score(stars: u32, mistakes: u32, forgotten: u32) -> GameScore {
+        GameScore { mistakes, forgotten, stars, accuracy: stars as f32 / 3.0, ..default() }
+    }
+
+    #[test]
+    fn key_leaves_out_the_asset_source() {
+        assert_eq!(path_key(&AssetPath::from("embedded://levels/level_1.level.ron")), "levels/level_1.level.ron");
+        assert_eq!(path_key(&AssetPath::from("levels/level_1.level.ron")), "levels/level_1.level.ron");
+    }
+
+    #[test]
+    fn best_score_is_kept() {
+        let mut progress = Progress::default();
+        progress.record_key("a".into(), None, &score(2, 1, 2), 30.0);
+        progress.record_key("a".into(), None, &score(2, 3, 1), 20.0);
+        assert_eq!(progress.records["a"].mistakes, 1);
+        assert_eq!(progress.records["a"].forgotten, 2);
+
+        progress.record_key("a".into(), None, &score(3, 0, 0), 40.0);
+        assert_eq!(progress.records["a"].stars, 3);
+        // A worse run keeps the record but can still improve the time
+        progress.record_key("a".into(), None, &score(1, 5, 5), 10.0);
+        assert_eq!(progress.records["a"].stars, 3);
+        assert_eq!(progress.records["a"].best_time, Some(10.0));
+    }
+
+    #[test]
+    fn best_time_is_only_kept_on_a_pass() {
+        let mut progress = Progress::default();
+        progress.record_key("a".into(), None, &score(0, 9, 9), 5.0);
+        assert!(!progress.records["a"].passed);
+        assert_eq!(progress.records["a"].best_time, None);
+
+        progress.record_key("a".into(), None, &score(1, 2, 2), 25.0);
+        progress.record_key("a".into(), None, &score(0, 0, 9), 15.0);
+        assert!(progress.records["a"].passed);
+        assert_eq!(progress.records["a"].best_time, Some(25.0));
+    }
+
+    #[test]
+    fn pass_unlocks_the_next_level() {
+        let mut progress = Progress::default();
+        progress.record_key("a".into(), Some("b".into()), &score(0, 9, 9), 5.0);
+        assert!(progress.unlocked.is_empty());
+
+        progress.record_key("a".into(), Some("b".into()), &score(1, 2, 2), 5.0);
+        assert!(progress.unlocked.contains("b"));
+        // The last level has nothing to unlock
+        progress.record_key("b".into(), None, &score(3, 0, 0), 5.0);
+        assert_eq!(progress.unlocked.len(), 1);
+    }
+}
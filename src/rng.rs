@@ -0,0 +1,23 @@
+/* SPDX-License-Identifier: MIT
+* Copyright (c) 2024 Elieva Pignat, Florian Depraz, Louis Mayencourt
+*/
+
+use bevy::prelude::*;
+use rand::{rngs::StdRng, SeedableRng};
+
+/// Random number generator of the game.
+/// It is reseeded when a level has a seed, so that the same field is built every time.
+#[derive(Resource, Deref, DerefMut)]
+pub struct GameRng(StdRng);
+
+impl Default for GameRng {
+    fn default() -> Self {
+        Self(StdRng::from_entropy())
+    }
+}
+
+impl GameRng {
+    pub fn reseed(&mut self, seed: u64) {
+        self.0 = StdRng::seed_from_u64(seed);
+    }
+}
@@ -0,0 +1,231 @@
+/* SPDX-License-Identifier: MIT
+* Copyright (c) 2024 Elieva Pignat, Florian Depraz, Louis Mayencourt
+*/
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::leaderboard::{Leaderboard, PlayerName};
+use crate::player::MovementSettings;
+use crate::progress::Progress;
+
+/// Version of the save file, raised when its content changes in a way older files cannot be read
+const SAVE_VERSION: u32 = 2;
+/// Name of the save file, in the data folder or in the browser storage
+const SAVE_FILE: &str = "save.ron";
+
+/// Where the save data and the configuration are kept between two runs of the game
+pub trait SaveStorage: Send + Sync {
+    /// Content of the save, `None` when there is none yet
+    fn read(&self) -> Option<String>;
+    fn write(&self, text: &str) -> Result<(), String>;
+}
+
+/// Save kept in a file of the data folder of the user
+#[cfg(not(target_arch = "wasm32"))]
+pub struct FileStorage {
+    path: std::path::PathBuf,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl SaveStorage for FileStorage {
+    fn read(&self) -> Option<String> {
+        std::fs::read_to_string(&self.path).ok()
+    }
+
+    fn write(&self, text: &str) -> Result<(), String> {
+        if let Some(dir) = self.path.parent() {
+            std::fs::create_dir_all(dir).map_err(|err| format!("{}: {}", dir.display(), err))?;
+        }
+        std::fs::write(&self.path, text).map_err(|err| format!("{}: {}", self.path.display(), err))
+    }
+}
+
+/// Save kept in the local storage of the browser, under `key`
+#[cfg(target_arch = "wasm32")]
+pub struct WebStorage {
+    key: &'static str,
+}
+
+#[cfg(target_arch = "wasm32")]
+impl WebStorage {
+    fn storage() -> Option<web_sys::Storage> {
+        web_sys::window()?.local_storage().ok()?
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
+impl SaveStorage for WebStorage {
+    fn read(&self) -> Option<String> {
+        Self::storage()?.get_item(self.key).ok()?
+    }
+
+    fn write(&self, text: &str) -> Result<(), String> {
+        let storage = Self::storage().ok_or("No local storage")?;
+        storage.set_item(self.key, text).map_err(|err| format!("{:?}", err))
+    }
+}
+
+/// Storage of the save of the platform, `None` when there is nowhere to keep it
+fn platform_storage() -> Option<Box<dyn SaveStorage>> {
+    #[cfg(target_arch = "wasm32")]
+    return Some(Box::new(WebStorage { key: SAVE_FILE }));
+
+    #[cfg(not(target_arch = "wasm32"))]
+    crate::config::data_dir().map(|dir| Box::new(FileStorage { path: dir.join(SAVE_FILE) }) as Box<dyn SaveStorage>)
+}
+
+/// Storage of the configuration file `name`, in the configuration folder or in the browser storage
+pub fn config_storage(name: &'static str) -> Option<Box<dyn SaveStorage>> {
+    #[cfg(target_arch = "wasm32")]
+    return Some(Box::new(WebStorage { key: name }));
+
+    #[cfg(not(target_arch = "wasm32"))]
+    crate::config::config_file(name).map(|path| Box::new(FileStorage { path }) as Box<dyn SaveStorage>)
+}
+
+/// Content of the save file
+#[derive(Debug, Deserialize, Serialize)]
+struct SaveData {
+    version: u32,
+    progress: Progress,
+    #[serde(default = "default_diagonals")]
+    diagonals: bool,
+    #[serde(default)]
+    leaderboard: Leaderboard,
+    #[serde(default)]
+    player_name: Option<String>,
+}
+
+fn default_diagonals() -> bool {
+    MovementSettings::default().diagonals
+}
+
+/// Read the save data from the content of the save
+fn parse_save(text: &str) -> Result<SaveData, String> {
+    let data = ron::de::from_str::<SaveData>(text).map_err(|err| err.to_string())?;
+    // Files of a newer game may hold things this one does not know about
+    if data.version > SAVE_VERSION {
+        return Err(format!("version {} is not supported, only {} is", data.version, SAVE_VERSION));
+    }
+    Ok(data)
+}
+
+/// Storage used to load and write the save data
+#[derive(Resource)]
+pub struct Save(Option<Box<dyn SaveStorage>>);
+
+/// Write the progress, the high scores and the settings to the save
+#[derive(Event, Default)]
+pub struct SaveGame;
+
+pub struct SavePlugin;
+
+impl Plugin for SavePlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(Save(platform_storage()));
+        app.add_event::<SaveGame>();
+        app.add_systems(Startup, load_save);
+        app.add_systems(Update, write_save);
+    }
+}
+
+/// Restore the progress, the high scores and the settings of the previous runs
+fn load_save(
+    save: Res<Save>,
+    mut progress: ResMut<Progress>,
+    mut movement: ResMut<MovementSettings>,
+    mut leaderboard: ResMut<Leaderboard>,
+    mut name: ResMut<PlayerName>,
+) {
+    let Some(text) = save.0.as_ref().and_then(|storage| storage.read()) else {
+        return;
+    };
+
+    match parse_save(&text) {
+        Ok(data) => {
+            *progress = data.progress;
+            movement.diagonals = data.diagonals;
+            *leaderboard = data.leaderboard;
+            if let Some(player_name) = data.player_name {
+                name.0 = player_name;
+            }
+        }
+        Err(err) => warn!("Ignoring the save: {}", err),
+    }
+}
+
+fn write_save(
+    mut event: EventReader<SaveGame>,
+    save: Res<Save>,
+    progress: Res<Progress>,
+    movement: Res<MovementSettings>,
+    leaderboard: Res<Leaderboard>,
+    name: Res<PlayerName>,
+) {
+    if !event.is_empty() {
+        event.clear();
+        let Some(storage) = save.0.as_ref() else {
+            return;
+        };
+
+        let data = SaveData {
+            version: SAVE_VERSION,
+            progress: progress.clone(),
+            diagonals: movement.diagonals,
+            leaderboard: leaderboard.clone(),
+            player_name: Some(name.0.clone()),
+        };
+        let result = ron::ser::to_string_pretty(&data, ron::ser::PrettyConfig::default())
+            .map_err(|err| err.to_string())
+            .and_then(|text| storage.write(&text));
+        if let Err(err) = result {
+            error!("Could not save the game: {}", err);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn save_data(version: u32) -> SaveData {
+        let mut progress = Progress::default();
+        progress.unlocked.insert("levels/level_2.level.ron".to_string());
+        SaveData {
+            version,
+            progress,
+            diagonals: false,
+            leaderboard: Leaderboard::default(),
+            player_name: Some("Ada".to_string()),
+        }
+    }
+
+    #[test]
+    fn save_is_read_back() {
+        let text = ron::ser::to_string_pretty(&save_data(SAVE_VERSION), ron::ser::PrettyConfig::default()).unwrap();
+        let data = parse_save(&text).unwrap();
+        assert_eq!(data.version, SAVE_VERSION);
+        assert!(data.progress.unlocked.contains("levels/level_2.level.ron"));
+        assert!(!data.diagonals);
+        assert_eq!(data.player_name.as_deref(), Some("Ada"));
+    }
+
+    #[test]
+    fn missing_settings_get_their_default() {
+        let data = parse_save("(version: 2, progress: (unlocked: [], records: {}))").unwrap();
+        assert_eq!(data.diagonals, default_diagonals());
+        assert_eq!(data.player_name, None);
+    }
+
+    #[test]
+    fn newer_save_is_ignored() {
+        let text = ron::ser::to_string(&save_data(SAVE_VERSION + 1)).unwrap();
+        assert!(parse_save(&text).is_err());
+    }
+
+    #[test]
+    fn broken_save_is_ignored() {
+        assert!(parse_save("(version: 2, progress: (").is_err());
+    }
+}
@@ -0,0 +1,192 @@
+/* SPDX-License-Identifier: MIT
+* Copyright (c) 2024 Elieva Pignat, Florian Depraz, Louis Mayencourt
+*/
+
+use bevy::{
+    color::palettes::css::{WHITE, YELLOW},
+    prelude::*,
+};
+
+use crate::GameState;
+use crate::input::{Action, Bindings};
+use crate::menu::ReturnState;
+use crate::player::MovementSettings;
+use crate::save::SaveGame;
+
+/// Lines of the settings screen, one per action then the movement options
+const ROWS: usize = Action::ALL.len() + 1;
+
+/// Position in the settings screen
+#[derive(Resource, Default)]
+struct SettingsMenu {
+    selected: usize,
+    /// The next key or button pressed is bound to the selected action
+    waiting: bool,
+    /// Actions which lost their key or button to the last binding
+    cleared: Vec<Action>,
+}
+
+/// Component to identify the root of the settings screen
+#[derive(Component)]
+struct SettingsScreen;
+
+/// Component to identify the text listing the bindings
+#[derive(Component)]
+struct BindingList;
+
+pub struct SettingsPlugin;
+
+impl Plugin for SettingsPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(Bindings::load());
+        app.init_resource::<SettingsMenu>();
+        app.add_systems(OnEnter(GameState::Settings), open_settings);
+        app.add_systems(OnExit(GameState::Settings), close_settings);
+        app.add_systems(Update, (edit_bindings, show_bindings).chain().run_if(in_state(GameState::Settings)));
+    }
+}
+
+fn open_settings(
+    mut commands: Commands,
+    mut menu: ResMut<SettingsMenu>,
+) {
+    *menu = SettingsMenu::default();
+
+    let text_style = TextStyle {
+        color: WHITE.into(),
+        font_size: 22.0,
+        ..default()
+    };
+
+    commands.spawn((
+        NodeBundle {
+            style: Style {
+                width: Val::Percent(100.0),
+                height: Val::Percent(100.0),
+                justify_content: JustifyContent::Center,
+                align_items: AlignItems::Center,
+                ..default()
+            },
+            background_color: BackgroundColor(Color::srgba(0.0, 0.0, 0.0, 0.8)),
+            ..default()
+        },
+        SettingsScreen,
+    ))
+    .with_children(|parent| {
+        let mut sections = vec![TextSection::new("SETTINGS\n\n", TextStyle { font_size: 40.0, ..text_style.clone() })];
+        sections.extend((0..ROWS).map(|_| TextSection::from_style(text_style.clone())));
+        sections.push(TextSection::from_style(TextStyle { color: YELLOW.into(), ..text_style.clone() }));
+        sections.push(TextSection::new(
+            "\nUp/Down: Select   Enter: Change   Backspace: Default   Escape: Back or cancel",
+            text_style.clone(),
+        ));
+        parent.spawn((TextBundle::from_sections(sections), BindingList));
+    });
+}
+
+/// Leave the screen and keep the bindings and the options for the next time
+fn close_settings(
+    mut commands: Commands,
+    screens: Query<Entity, With<SettingsScreen>>,
+    bindings: Res<Bindings>,
+    mut save: EventWriter<SaveGame>,
+) {
+    for screen in screens.iter() {
+        commands.entity(screen).despawn_recursive();
+    }
+    bindings.save();
+    save.send_default();
+}
+
+/// The screen is navigated with fixed keys and buttons, to never lock the player out
+fn edit_bindings(
+    keyboard: Res<ButtonInput<KeyCode>>,
+    gamepad_buttons: Res<ButtonInput<GamepadButton>>,
+    mut menu: ResMut<SettingsMenu>,
+    mut bindings: ResMut<Bindings>,
+    mut movement: ResMut<MovementSettings>,
+    mut next_state: ResMut<NextState<GameState>>,
+    exit: Res<ReturnState>,
+) {
+    // The last line is the diagonal moves, not an action
+    let action = Action::ALL.get(menu.selected).copied();
+
+    if let Some(action) = action.filter(|_| menu.waiting) {
+        // Escape is kept to cancel, it cannot be bound from here
+        if keyboard.just_pressed(KeyCode::Escape) {
+            menu.waiting = false;
+        } else if let Some(key) = keyboard.get_just_pressed().next() {
+            let mut binding = bindings.get(action).clone();
+            binding.keys = vec![*key];
+            menu.cleared = bindings.set(action, binding);
+            menu.waiting = false;
+        } else if let Some(button) = gamepad_buttons.get_just_pressed().next() {
+            let mut binding = bindings.get(action).clone();
+            binding.buttons = vec![button.button_type];
+            menu.cleared = bindings.set(action, binding);
+            menu.waiting = false;
+        }
+        return;
+    }
+
+    let pressed = |key: KeyCode, button: GamepadButtonType| {
+        keyboard.just_pressed(key) || gamepad_buttons.get_just_pressed().any(|pressed| pressed.button_type == button)
+    };
+
+    if pressed(KeyCode::ArrowUp, GamepadButtonType::DPadUp) {
+        menu.selected = (menu.selected + ROWS - 1) % ROWS;
+    } else if pressed(KeyCode::ArrowDown, GamepadButtonType::DPadDown) {
+        menu.selected = (menu.selected + 1) % ROWS;
+    } else if pressed(KeyCode::Enter, GamepadButtonType::South) {
+        menu.cleared.clear();
+        match action {
+            Some(_) => menu.waiting = true,
+            None => movement.diagonals = !movement.diagonals,
+        }
+    } else if pressed(KeyCode::Backspace, GamepadButtonType::West) {
+        match action {
+            Some(action) => {
+                let default = Bindings::default().get(action).clone();
+                menu.cleared = bindings.set(action, default);
+            }
+            None => *movement = MovementSettings::default(),
+        }
+    } else if pressed(KeyCode::Escape, GamepadButtonType::East) {
+        next_state.set(exit.0.clone());
+    }
+}
+
+fn show_bindings(
+    menu: Res<SettingsMenu>,
+    bindings: Res<Bindings>,
+    movement: Res<MovementSettings>,
+    mut list: Query<&mut Text, With<BindingList>>,
+) {
+    if !menu.is_changed() && !bindings.is_changed() && !movement.is_changed() {
+        return;
+    }
+
+    for mut text in list.iter_mut() {
+        for (idx, action) in Action::ALL.iter().enumerate() {
+            let section = &mut text.sections[idx + 1];
+            if idx == menu.selected && menu.waiting {
+                section.value = format!("> {}: press a key or a button\n", action.name());
+            } else {
+                section.value = format!("  {}: {}\n", action.name(), bindings.get(*action).describe());
+            }
+            section.style.color = if idx == menu.selected { YELLOW.into() } else { WHITE.into() };
+        }
+
+        let section = &mut text.sections[ROWS];
+        let diagonals = if movement.diagonals { "On" } else { "Off" };
+        section.value = format!("\n  Diagonal moves: {}\n", diagonals);
+        section.style.color = if menu.selected == ROWS - 1 { YELLOW.into() } else { WHITE.into() };
+
+        let names: Vec<&str> = menu.cleared.iter().map(|action| action.name()).collect();
+        text.sections[ROWS + 1].value = if names.is_empty() {
+            String::new()
+        } else {
+            format!("\n{} lost this key or button, bind it again\n", names.join(", "))
+        };
+    }
+}
@@ -4,6 +4,8 @@ use bevy::sprite::Mesh2dHandle;
 
 use rand::Rng;
 
+use crate::rng::GameRng;
+
 pub struct SkyPlugin;
 
 #[derive(Component)]
@@ -31,9 +33,9 @@ fn setupstaticstar(
     mut commands: Commands,
     mut meshes: ResMut<Assets<Mesh>>,
     mut materials: ResMut<Assets<StandardMaterial>>,
+    mut rng: ResMut<GameRng>,
 ) {
     // Generate stars
-    let mut rng: rand::prelude::ThreadRng = rand::thread_rng();
     let num_stars = 2000;
     const STARFIELD_SIZE: Vec3 = Vec3::new(800.0, 600.0, 600.0);
 
@@ -60,9 +62,9 @@ fn setupstar(
     mut commands: Commands,
     mut meshes: ResMut<Assets<Mesh>>,
     mut materials: ResMut<Assets<StandardMaterial>>,
+    mut rng: ResMut<GameRng>,
 ) {
     let num_stars = 60;
-    let mut rng: rand::prelude::ThreadRng = rand::thread_rng();
     const STARFIELD_SIZE: Vec3 = Vec3::new(800.0, 600.0, 600.0);
 
     for _ in 0..num_stars {
@@ -136,6 +138,7 @@ fn draw_ghost_trail(
     mut materials: ResMut<Assets<ColorMaterial>>,
     mut query: Query<(&mut Transform, &Star)>,
     mut trail_query: Query<(&mut GhostTrail, &mut Transform, Entity), Without<Star>>,
+    mut rng: ResMut<GameRng>,
 ) {
     for (mut trail, mut transform, entity) in &mut trail_query {
         if trail.life > 0 {
@@ -149,7 +152,6 @@ fn draw_ghost_trail(
 
     for (visible, star) in query.iter_mut() {
         if star.hasTrail {
-            let mut rng: rand::prelude::ThreadRng = rand::thread_rng();
             let size = rng.gen_range(0.1..0.3);
 
             // Spawn a new trail at the current pendulum position
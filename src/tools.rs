@@ -0,0 +1,277 @@
+/* SPDX-License-Identifier: MIT
+* Copyright (c) 2024 Elieva Pignat, Florian Depraz, Louis Mayencourt
+*/
+
+use bevy::prelude::*;
+
+use crate::GameState;
+use crate::assist::Assist;
+use crate::history::History;
+use crate::input::{Action, GameInput};
+use crate::menu::RestartGame;
+use crate::player::Player;
+use crate::world::CellChanged;
+use crate::world::grid::FieldGrid;
+
+/// How the space bar cuts the corn
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ToolKind {
+    /// Cut under the brush, where the player is
+    #[default]
+    Brush,
+    /// Cut a straight line between two marked cells
+    Line,
+    /// Cut a circle around the first marked cell, going through the second one
+    Circle,
+}
+
+impl ToolKind {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Brush => "Brush",
+            Self::Line => "Line",
+            Self::Circle => "Circle",
+        }
+    }
+
+    fn next(&self) -> Self {
+        match self {
+            Self::Brush => Self::Line,
+            Self::Line => Self::Circle,
+            Self::Circle => Self::Brush,
+        }
+    }
+
+    /// Cells of the shape between the two marked cells
+    pub fn cells(&self, start: IVec2, end: IVec2) -> Vec<IVec2> {
+        match self {
+            Self::Brush => Vec::new(),
+            Self::Line => line_cells(start, end),
+            Self::Circle => circle_cells(start, start.as_vec2().distance(end.as_vec2()).round() as i32),
+        }
+    }
+}
+
+/// Tool used by the player, with the first cell marked for the line and circle
+#[derive(Resource, Default)]
+pub struct DrawTool {
+    pub kind: ToolKind,
+    pub start: Option<IVec2>,
+}
+
+/// Component to identify the tiles showing the shape being drawn
+#[derive(Component)]
+struct ToolPreview;
+
+/// Mesh and material shared by the preview tiles
+#[derive(Resource)]
+struct PreviewAssets {
+    mesh: Handle<Mesh>,
+    material: Handle<StandardMaterial>,
+}
+
+pub struct ToolsPlugin;
+
+impl Plugin for ToolsPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<DrawTool>();
+        app.add_systems(Startup, setup_preview);
+        app.add_systems(Update, (reset_tool, use_tool, preview_tool).chain());
+    }
+}
+
+/// Cells of the line from `start` to `end`, with the Bresenham algorithm
+pub fn line_cells(start: IVec2, end: IVec2) -> Vec<IVec2> {
+    let delta = (end - start).abs();
+    let step = (end - start).signum();
+    let mut error = delta.x - delta.y;
+    let mut cell = start;
+
+    let mut cells = vec![cell];
+    while cell != end {
+        let error2 = 2 * error;
+        if error2 > -delta.y {
+            error -= delta.y;
+            cell.x += step.x;
+        }
+        if error2 < delta.x {
+            error += delta.x;
+            cell.y += step.y;
+        }
+        cells.push(cell);
+    }
+    cells
+}
+
+/// Cells of the circle around `center`, with the midpoint algorithm
+pub fn circle_cells(center: IVec2, radius: i32) -> Vec<IVec2> {
+    let mut cells = Vec::new();
+    let mut x = radius;
+    let mut y = 0;
+    let mut error = 1 - radius;
+
+    while x >= y {
+        // Every point is repeated in the eight octants
+        for (dx, dy) in [(x, y), (y, x), (-y, x), (-x, y), (-x, -y), (-y, -x), (y, -x), (x, -y)] {
+            let cell = center + IVec2::new(dx, dy);
+            if !cells.contains(&cell) {
+                cells.push(cell);
+            }
+        }
+        y += 1;
+        if error < 0 {
+            error += 2 * y + 1;
+        } else {
+            x -= 1;
+            error += 2 * (y - x) + 1;
+        }
+    }
+    cells
+}
+
+fn setup_preview(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+) {
+    commands.insert_resource(PreviewAssets {
+        mesh: meshes.add(Cuboid::new(0.9, 0.05, 0.9)),
+        material: materials.add(Color::srgba(0.0, 1.0, 1.0, 0.35)),
+    });
+}
+
+/// Switch tool, mark the first cell then cut the shape, or forget the mark
+#[allow(clippy::too_many_arguments)]
+fn use_tool(
+    input: GameInput,
+    state: Res<State<GameState>>,
+    player: Query<&Player>,
+    mut tool: ResMut<DrawTool>,
+    mut field: ResMut<FieldGrid>,
+    mut history: ResMut<History>,
+    mut changed: EventWriter<CellChanged>,
+    mut assist: ResMut<Assist>,
+) {
+    if *state.get() != GameState::InGame {
+        return;
+    }
+    let player = player.single();
+
+    if input.just_pressed(Action::Tool) {
+        tool.kind = tool.kind.next();
+        tool.start = None;
+    }
+    if input.just_pressed(Action::CancelTool) {
+        tool.start = None;
+    }
+
+    if tool.kind != ToolKind::Brush && input.just_pressed(Action::Cut) {
+        match tool.start.take() {
+            None => tool.start = Some(player.cell),
+            Some(start) => {
+                let cut = assist.cut(tool.kind.cells(start, player.cell), &mut history, &mut field);
+                changed.send_batch(cut.into_iter().map(|cell| CellChanged { cell }));
+            }
+        }
+    }
+}
+
+fn reset_tool(
+    mut event: EventReader<RestartGame>,
+    mut tool: ResMut<DrawTool>,
+) {
+    if !event.is_empty() {
+        event.clear();
+        tool.start = None;
+    }
+}
+
+/// Show the shape that would be cut, from the marked cell to the player
+#[allow(clippy::too_many_arguments)]
+fn preview_tool(
+    mut commands: Commands,
+    state: Res<State<GameState>>,
+    player: Query<&Player>,
+    tool: Res<DrawTool>,
+    field: Res<FieldGrid>,
+    previews: Query<Entity, With<ToolPreview>>,
+    preview_assets: Res<PreviewAssets>,
+    mut shown: Local<Option<(ToolKind, IVec2, IVec2)>>,
+) {
+    let player = player.single();
+    let wanted = match tool.start {
+        Some(start) if *state.get() == GameState::InGame => Some((tool.kind, start, player.cell)),
+        _ => None,
+    };
+    if *shown == wanted {
+        return;
+    }
+    *shown = wanted;
+
+    for preview in previews.iter() {
+        commands.entity(preview).despawn();
+    }
+
+    if let Some((kind, start, end)) = wanted {
+        for cell in kind.cells(start, end) {
+            if field.is_walkable(cell) {
+                commands.spawn((
+                    PbrBundle {
+                        mesh: preview_assets.mesh.clone(),
+                        material: preview_assets.material.clone(),
+                        transform: Transform::from_xyz(cell.x as f32, 0.1, cell.y as f32),
+                        ..default()
+                    },
+                    ToolPreview,
+                ));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn line_goes_from_start_to_end() {
+        let cells = line_cells(IVec2::new(1, 1), IVec2::new(5, 3));
+        assert_eq!(cells.first(), Some(&IVec2::new(1, 1)));
+        assert_eq!(cells.last(), Some(&IVec2::new(5, 3)));
+        // One cell per column on a mostly horizontal line
+        assert_eq!(cells.len(), 5);
+        // Every cell touches the previous one
+        assert!(cells.windows(2).all(|pair| (pair[1] - pair[0]).abs().max_element() == 1));
+    }
+
+    #[test]
+    fn diagonal_line_moves_on_both_axes() {
+        let cells = line_cells(IVec2::new(3, 0), IVec2::new(0, 3));
+        assert_eq!(cells, vec![IVec2::new(3, 0), IVec2::new(2, 1), IVec2::new(1, 2), IVec2::new(0, 3)]);
+    }
+
+    #[test]
+    fn line_of_a_single_cell() {
+        assert_eq!(line_cells(IVec2::new(2, 2), IVec2::new(2, 2)), vec![IVec2::new(2, 2)]);
+    }
+
+    #[test]
+    fn circle_of_radius_zero_is_the_center() {
+        assert_eq!(circle_cells(IVec2::new(4, 4), 0), vec![IVec2::new(4, 4)]);
+    }
+
+    #[test]
+    fn circle_cells_are_at_the_radius() {
+        let center = IVec2::new(10, 10);
+        let cells = circle_cells(center, 5);
+        for cell in &cells {
+            let distance = cell.as_vec2().distance(center.as_vec2());
+            assert!((distance - 5.0).abs() < 1.0, "{cell} is at {distance}");
+        }
+        for (idx, cell) in cells.iter().enumerate() {
+            assert!(!cells[idx + 1..].contains(cell), "{cell} is repeated");
+        }
+        assert!(cells.contains(&IVec2::new(15, 10)));
+        assert!(cells.contains(&IVec2::new(10, 5)));
+    }
+}
@@ -0,0 +1,234 @@
+/* SPDX-License-Identifier: MIT
+ * Copyright (c) 2024 Elieva Pignat, Florian Depraz, Louis Mayencourt
+ */
+
+use std::f32::consts::TAU;
+
+use bevy::prelude::*;
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
+
+use crate::world::grid::{CellState, FieldGrid};
+
+/// Parameters of a generated crop circle
+#[derive(Clone, Copy, Debug, Deserialize, Serialize)]
+pub struct PatternGenerator {
+    pub seed: u64,
+    /// From 0.0 for a few simple rings to 1.0 for dense formations
+    pub difficulty: f32,
+}
+
+/// Symmetry applied to the shapes of a formation
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Symmetry {
+    /// Copies rotated around the center of the field
+    Rotational(u32),
+    /// Same as `Rotational`, with every copy also mirrored
+    Dihedral(u32),
+}
+
+/// Element of a formation, in cell coordinates
+#[derive(Clone, Debug)]
+pub enum Shape {
+    /// A line following the points, one cell wide
+    Stroke(Vec<Vec2>),
+    /// A filled disc
+    Disc { center: Vec2, radius: f32 },
+}
+
+impl Shape {
+    pub fn ring(center: Vec2, radius: f32) -> Self {
+        Self::arc(center, radius, 0.0, TAU)
+    }
+
+    /// Part of a ring, from the angle `start` to `end` in radians
+    pub fn arc(center: Vec2, radius: f32, start: f32, end: f32) -> Self {
+        // Sample often enough to not leave holes between the cells
+        let steps = ((end - start).abs() * radius * 3.0).ceil().max(1.0) as usize;
+        Self::Stroke((0..=steps).map(|i| {
+            let angle = start + (end - start) * i as f32 / steps as f32;
+            center + radius * Vec2::from_angle(angle)
+        }).collect())
+    }
+
+    /// Straight line going out of the center in the direction `angle`
+    pub fn spoke(center: Vec2, angle: f32, from: f32, to: f32) -> Self {
+        let direction = Vec2::from_angle(angle);
+        Self::line(center + from * direction, center + to * direction)
+    }
+
+    pub fn line(start: Vec2, end: Vec2) -> Self {
+        let steps = (start.distance(end) * 3.0).ceil().max(1.0) as usize;
+        Self::Stroke((0..=steps).map(|i| start.lerp(end, i as f32 / steps as f32)).collect())
+    }
+
+    /// Cubic Bézier curve
+    pub fn bezier(p0: Vec2, p1: Vec2, p2: Vec2, p3: Vec2) -> Self {
+        let length = p0.distance(p1) + p1.distance(p2) + p2.distance(p3);
+        let steps = (length * 3.0).ceil().max(1.0) as usize;
+        Self::Stroke((0..=steps).map(|i| {
+            let t = i as f32 / steps as f32;
+            let u = 1.0 - t;
+            u * u * u * p0 + 3.0 * u * u * t * p1 + 3.0 * u * t * t * p2 + t * t * t * p3
+        }).collect())
+    }
+
+    /// Same shape with all its points moved by `f`
+    pub fn map(&self, f: impl Fn(Vec2) -> Vec2) -> Self {
+        match self {
+            Self::Stroke(points) => Self::Stroke(points.iter().map(|p| f(*p)).collect()),
+            Self::Disc { center, radius } => Self::Disc { center: f(*center), radius: *radius },
+        }
+    }
+
+    /// Cut the cells covered by the shape
+    pub fn draw(&self, field: &mut FieldGrid) {
+        match self {
+            Self::Stroke(points) => {
+                for point in points {
+                    field.set(point.round().as_ivec2(), CellState::Cut);
+                }
+            }
+            Self::Disc { center, radius } => {
+                let min = (*center - *radius).floor().as_ivec2();
+                let max = (*center + *radius).ceil().as_ivec2();
+                for x in min.x..=max.x {
+                    for y in min.y..=max.y {
+                        let cell = IVec2::new(x, y);
+                        if cell.as_vec2().distance(*center) <= *radius {
+                            field.set(cell, CellState::Cut);
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl Symmetry {
+    /// All the copies of a shape drawn around `center`
+    pub fn apply(&self, shape: &Shape, center: Vec2) -> Vec<Shape> {
+        let (folds, mirror) = match *self {
+            Self::Rotational(folds) => (folds.max(1), false),
+            Self::Dihedral(folds) => (folds.max(1), true),
+        };
+
+        let mut shapes = Vec::new();
+        for fold in 0..folds {
+            let rotation = Vec2::from_angle(TAU * fold as f32 / folds as f32);
+            shapes.push(shape.map(|p| center + rotation.rotate(p - center)));
+            if mirror {
+                shapes.push(shape.map(|p| {
+                    let p = p - center;
+                    center + rotation.rotate(Vec2::new(p.x, -p.y))
+                }));
+            }
+        }
+        shapes
+    }
+}
+
+impl PatternGenerator {
+    /// Generate a formation filling a `width` x `height` field.
+    /// The same seed, difficulty and size always give the same pattern.
+    pub fn generate(&self, width: usize, height: usize) -> FieldGrid {
+        let mut rng = StdRng::seed_from_u64(self.seed);
+        let difficulty = self.difficulty.clamp(0.0, 1.0);
+
+        let mut field = FieldGrid::new(width, height);
+
+        let center = Vec2::new((width as f32 - 1.0) / 2.0, (height as f32 - 1.0) / 2.0);
+        // Keep a margin around the formation so it does not touch the border
+        let max_radius = (width.min(height) as f32 / 2.0 - 1.5).max(1.0);
+
+        // More folds and more elements with the difficulty
+        let folds = *[1, 2, 3, 4, 4, 5, 6, 8]
+            .get(rng.gen_range(0..=(2.0 + difficulty * 5.0) as usize))
+            .unwrap_or(&4);
+        let symmetry = if rng.gen_bool(0.5) {
+            Symmetry::Dihedral(folds)
+        } else {
+            Symmetry::Rotational(folds)
+        };
+        let sector = TAU / folds as f32;
+
+        let mut shapes = Vec::new();
+
+        // Every formation is built around a main ring
+        let main_radius = max_radius * rng.gen_range(0.55..0.9);
+        shapes.push(Shape::ring(center, main_radius));
+
+        if rng.gen_bool(0.5) {
+            shapes.push(Shape::Disc { center, radius: rng.gen_range(0.5..(max_radius * 0.2).max(0.6)) });
+        } else {
+            shapes.push(Shape::ring(center, main_radius * rng.gen_range(0.2..0.45)));
+        }
+
+        // Small fields get crowded quickly, limit the number of elements with the size
+        let elements = 1 + (difficulty * (max_radius / 3.0).min(5.0)).round() as usize;
+        for _ in 0..elements {
+            let angle = rng.gen_range(0.0..sector);
+            // Only one copy of the motif is created, the symmetry builds the others
+            let motif = match rng.gen_range(0..4) {
+                0 => Shape::spoke(center, angle, main_radius * rng.gen_range(0.0..0.5), max_radius),
+                1 => {
+                    let radius = max_radius * rng.gen_range(0.3..1.0);
+                    Shape::arc(center, radius, angle, angle + sector * rng.gen_range(0.2..0.8))
+                }
+                2 => {
+                    let start = center + main_radius * Vec2::from_angle(angle);
+                    let end = center + max_radius * rng.gen_range(0.3..1.0) * Vec2::from_angle(angle + sector * 0.5);
+                    let bend = max_radius * 0.4;
+                    Shape::bezier(
+                        start,
+                        start + bend * Vec2::from_angle(angle + rng.gen_range(-1.0..1.0)),
+                        end + bend * Vec2::from_angle(angle + sector + rng.gen_range(-1.0..1.0)),
+                        end,
+                    )
+                }
+                _ => {
+                    // Small satellite circle on the main ring, common in real formations
+                    let satellite = center + main_radius * Vec2::from_angle(angle);
+                    let radius = (max_radius * rng.gen_range(0.1..0.25)).max(1.0);
+                    if rng.gen_bool(0.5) {
+                        Shape::ring(satellite, radius)
+                    } else {
+                        Shape::Disc { center: satellite, radius: radius * 0.6 }
+                    }
+                }
+            };
+            shapes.extend(symmetry.apply(&motif, center));
+        }
+
+        for shape in shapes {
+            shape.draw(&mut field);
+        }
+
+        field
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn same_seed_gives_the_same_pattern() {
+        let generator = PatternGenerator { seed: 42, difficulty: 0.7 };
+        assert_eq!(generator.generate(31, 25), generator.generate(31, 25));
+    }
+
+    #[test]
+    fn other_seeds_give_other_patterns() {
+        let pattern = PatternGenerator { seed: 1, difficulty: 0.5 }.generate(31, 31);
+        assert!((2..10).any(|seed| PatternGenerator { seed, difficulty: 0.5 }.generate(31, 31) != pattern));
+    }
+
+    #[test]
+    fn pattern_fills_the_field() {
+        let pattern = PatternGenerator { seed: 7, difficulty: 1.0 }.generate(21, 15);
+        assert_eq!(pattern.width(), 21);
+        assert_eq!(pattern.height(), 15);
+        assert!(pattern.iter().any(|(_, state)| state == CellState::Cut));
+    }
+}
@@ -0,0 +1,169 @@
+/* SPDX-License-Identifier: MIT
+ * Copyright (c) 2024 Elieva Pignat, Florian Depraz, Louis Mayencourt
+ */
+
+use bevy::prelude::*;
+
+/// State of one cell of the field
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CellState {
+    Standing,
+    Cut,
+    Obstacle,
+}
+
+/// Logical state of the field, source of truth for the game logic.
+/// Cells are addressed with `IVec2 { x, y }` where `y` is the Z axis of the world.
+#[derive(Resource, Clone, Debug, Default, PartialEq, Eq)]
+pub struct FieldGrid {
+    width: usize,
+    height: usize,
+    cells: Vec<CellState>,
+}
+
+/// Cells that differ between the field and the target pattern
+#[derive(Debug, Default)]
+pub struct FieldDiff {
+    /// Cut cells that should be standing
+    pub mistakes: Vec<IVec2>,
+    /// Standing cells that should be cut
+    pub forgotten: Vec<IVec2>,
+}
+
+impl FieldGrid {
+    /// Create a field full of standing corn
+    pub fn new(width: usize, height: usize) -> Self {
+        Self {
+            width,
+            height,
+            cells: vec![CellState::Standing; width * height],
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn contains(&self, cell: IVec2) -> bool {
+        cell.x >= 0 && cell.y >= 0 && (cell.x as usize) < self.width && (cell.y as usize) < self.height
+    }
+
+    fn index(&self, cell: IVec2) -> Option<usize> {
+        if self.contains(cell) {
+            Some(cell.y as usize * self.width + cell.x as usize)
+        } else {
+            None
+        }
+    }
+
+    /// State of the cell, `None` outside of the field
+    pub fn get(&self, cell: IVec2) -> Option<CellState> {
+        self.index(cell).map(|idx| self.cells[idx])
+    }
+
+    pub fn set(&mut self, cell: IVec2, state: CellState) {
+        if let Some(idx) = self.index(cell) {
+            self.cells[idx] = state;
+        }
+    }
+
+    /// The player can move on every cell of the field except the obstacles
+    pub fn is_walkable(&self, cell: IVec2) -> bool {
+        matches!(self.get(cell), Some(CellState::Standing | CellState::Cut))
+    }
+
+    /// Iterate over all the cells, line by line
+    pub fn iter(&self) -> impl Iterator<Item = (IVec2, CellState)> + '_ {
+        self.cells.iter().enumerate().map(|(idx, state)| {
+            (IVec2::new((idx % self.width) as i32, (idx / self.width) as i32), *state)
+        })
+    }
+
+    /// Field at the start of a level: every cell standing except the obstacles of the pattern
+    pub fn start_of(pattern: &FieldGrid) -> Self {
+        Self {
+            width: pattern.width,
+            height: pattern.height,
+            cells: pattern.cells.iter().map(|state| match state {
+                CellState::Obstacle => CellState::Obstacle,
+                _ => CellState::Standing,
+            }).collect(),
+        }
+    }
+
+    /// Compare the field with the target pattern, obstacles are never counted
+    pub fn diff(&self, target: &FieldGrid) -> FieldDiff {
+        let mut diff = FieldDiff::default();
+        for (cell, wanted) in target.iter() {
+            match (wanted, self.get(cell)) {
+                (CellState::Standing, Some(CellState::Cut)) => diff.mistakes.push(cell),
+                (CellState::Cut, Some(CellState::Standing)) => diff.forgotten.push(cell),
+                _ => (),
+            }
+        }
+        diff
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn contains_only_the_cells_of_the_field() {
+        let field = FieldGrid::new(3, 2);
+        assert!(field.contains(IVec2::new(0, 0)));
+        assert!(field.contains(IVec2::new(2, 1)));
+        assert!(!field.contains(IVec2::new(3, 0)));
+        assert!(!field.contains(IVec2::new(0, 2)));
+        assert!(!field.contains(IVec2::new(-1, 0)));
+        assert!(!field.contains(IVec2::new(0, -1)));
+    }
+
+    #[test]
+    fn start_of_keeps_only_the_obstacles() {
+        let mut pattern = FieldGrid::new(3, 3);
+        pattern.set(IVec2::new(0, 0), CellState::Cut);
+        pattern.set(IVec2::new(1, 1), CellState::Obstacle);
+
+        let start = FieldGrid::start_of(&pattern);
+        assert_eq!(start.width(), 3);
+        assert_eq!(start.height(), 3);
+        assert_eq!(start.get(IVec2::new(0, 0)), Some(CellState::Standing));
+        assert_eq!(start.get(IVec2::new(1, 1)), Some(CellState::Obstacle));
+        assert_eq!(start.iter().filter(|(_, state)| *state == CellState::Standing).count(), 8);
+    }
+
+    #[test]
+    fn diff_counts_mistakes_and_forgotten_cells() {
+        let mut pattern = FieldGrid::new(3, 3);
+        pattern.set(IVec2::new(0, 0), CellState::Cut);
+        pattern.set(IVec2::new(1, 0), CellState::Cut);
+        pattern.set(IVec2::new(2, 2), CellState::Obstacle);
+
+        let mut field = FieldGrid::start_of(&pattern);
+        assert_eq!(field.diff(&pattern).forgotten.len(), 2);
+        assert!(field.diff(&pattern).mistakes.is_empty());
+
+        field.set(IVec2::new(0, 0), CellState::Cut);
+        field.set(IVec2::new(1, 1), CellState::Cut);
+        let diff = field.diff(&pattern);
+        assert_eq!(diff.mistakes, vec![IVec2::new(1, 1)]);
+        assert_eq!(diff.forgotten, vec![IVec2::new(1, 0)]);
+    }
+
+    #[test]
+    fn diff_of_the_pattern_is_empty() {
+        let mut pattern = FieldGrid::new(2, 2);
+        pattern.set(IVec2::new(1, 0), CellState::Cut);
+        pattern.set(IVec2::new(0, 1), CellState::Obstacle);
+
+        let diff = pattern.clone().diff(&pattern);
+        assert!(diff.mistakes.is_empty());
+        assert!(diff.forgotten.is_empty());
+    }
+}
@@ -0,0 +1,229 @@
+/* SPDX-License-Identifier: MIT
+ * Copyright (c) 2024 Elieva Pignat, Florian Depraz, Louis Mayencourt
+ */
+
+use bevy::{
+    asset::{io::Reader, AssetLoader, AsyncReadExt, LoadContext},
+    prelude::*,
+};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::world::grid::{CellState, FieldGrid};
+use crate::world::levels::{Level, LevelFile};
+
+/// How an image is turned into a pattern
+#[derive(Clone, Copy, Debug, Deserialize, Serialize)]
+pub struct ImportSettings {
+    /// Size of the pattern, 0 to use one pixel per cell
+    pub width: usize,
+    pub height: usize,
+    /// Cells brighter than this (from 0.0 to 1.0) are cut
+    pub threshold: f32,
+    /// Cut the dark cells instead of the bright ones
+    pub invert: bool,
+}
+
+impl Default for ImportSettings {
+    fn default() -> Self {
+        Self {
+            width: 0,
+            height: 0,
+            threshold: 0.5,
+            invert: false,
+        }
+    }
+}
+
+#[non_exhaustive]
+#[derive(Debug, Error)]
+pub enum ImportError {
+    #[error("Could not read image: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Could not decode image: {0}")]
+    Image(#[from] image::ImageError),
+    #[error("Image of {0}x{1} pixels is too small for a {2}x{3} pattern")]
+    TooSmall(u32, u32, usize, usize),
+}
+
+/// Build a pattern from an image, drawn like the level previews: bright for the cells to cut,
+/// dark for the corn to keep. Every cell takes the average brightness of its pixels,
+/// transparent pixels count as dark.
+pub fn pattern_from_image(bytes: &[u8], settings: &ImportSettings) -> Result<FieldGrid, ImportError> {
+    let image = image::load_from_memory(bytes)?.to_rgba8();
+    let (image_width, image_height) = image.dimensions();
+
+    let width = if settings.width == 0 { image_width as usize } else { settings.width };
+    let height = if settings.height == 0 { image_height as usize } else { settings.height };
+    if (image_width as usize) < width || (image_height as usize) < height {
+        return Err(ImportError::TooSmall(image_width, image_height, width, height));
+    }
+
+    let mut pattern = FieldGrid::new(width, height);
+    for y in 0..height {
+        for x in 0..width {
+            let x_range = (x * image_width as usize / width) as u32..((x + 1) * image_width as usize / width) as u32;
+            let y_range = (y * image_height as usize / height) as u32..((y + 1) * image_height as usize / height) as u32;
+
+            let mut brightness = 0.0;
+            let mut count = 0.0;
+            for py in y_range {
+                for px in x_range.clone() {
+                    let [r, g, b, a] = image.get_pixel(px, py).0;
+                    let luma = 0.2126 * r as f32 + 0.7152 * g as f32 + 0.0722 * b as f32;
+                    brightness += luma / 255.0 * a as f32 / 255.0;
+                    count += 1.0;
+                }
+            }
+
+            let bright = brightness / count >= settings.threshold;
+            if bright != settings.invert {
+                pattern.set(IVec2::new(x as i32, y as i32), CellState::Cut);
+            }
+        }
+    }
+
+    Ok(pattern)
+}
+
+/// Loads a `.level.png` image directly as a level, with the star thresholds of a medium level
+#[derive(Default)]
+pub struct LevelImageLoader;
+
+impl AssetLoader for LevelImageLoader {
+    type Asset = Level;
+    type Settings = ImportSettings;
+    type Error = ImportError;
+
+    async fn load<'a>(
+        &'a self,
+        reader: &'a mut Reader<'_>,
+        settings: &'a ImportSettings,
+        _load_context: &'a mut LoadContext<'_>,
+    ) -> Result<Self::Asset, Self::Error> {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes).await?;
+        let pattern = pattern_from_image(&bytes, settings)?;
+        Ok(Level::from_pattern(pattern, 0.5))
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["level.png"]
+    }
+}
+
+/// Command line importer, writes a level file from an image:
+/// `corn-field import <image> <width> <height> <level file> [threshold] [--invert]`
+pub fn run_cli(args: &[String]) -> Result<(), String> {
+    let invert = args.iter().any(|arg| arg == "--invert");
+    let args: Vec<&String> = args.iter().filter(|arg| *arg != "--invert").collect();
+    let [image_path, width, height, level_path, rest @ ..] = args.as_slice() else {
+        return Err("usage: corn-field import <image> <width> <height> <level file> [threshold] [--invert]".to_string());
+    };
+
+    let mut settings = ImportSettings {
+        width: width.parse().map_err(|_| format!("Invalid width {}", width))?,
+        height: height.parse().map_err(|_| format!("Invalid height {}", height))?,
+        invert,
+        ..default()
+    };
+    if let Some(threshold) = rest.first() {
+        settings.threshold = threshold.parse().map_err(|_| format!("Invalid threshold {}", threshold))?;
+    }
+
+    let bytes = std::fs::read(image_path).map_err(|err| format!("{}: {}", image_path, err))?;
+    let pattern = pattern_from_image(&bytes, &settings).map_err(|err| err.to_string())?;
+    let level = LevelFile::from_level(&Level::from_pattern(pattern, 0.5));
+
+    let text = ron::ser::to_string_pretty(&level, ron::ser::PrettyConfig::default())
+        .map_err(|err| err.to_string())?;
+    std::fs::write(level_path, text).map_err(|err| format!("{}: {}", level_path, err))?;
+
+    println!("Level written to {}", level_path);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    use image::{DynamicImage, ImageFormat, Rgba, RgbaImage};
+
+    /// Pattern of a level file, the other fields are ignored
+    #[derive(Deserialize)]
+    struct LevelPattern {
+        pattern: Vec<String>,
+    }
+
+    fn png(image: RgbaImage) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        DynamicImage::ImageRgba8(image).write_to(&mut Cursor::new(&mut bytes), ImageFormat::Png).unwrap();
+        bytes
+    }
+
+    /// 4x2 image, white on the left half and black on the right one
+    fn half_white() -> Vec<u8> {
+        png(RgbaImage::from_fn(4, 2, |x, _| if x < 2 { Rgba([255; 4]) } else { Rgba([0, 0, 0, 255]) }))
+    }
+
+    #[test]
+    fn bright_pixels_are_cut() {
+        let pattern = pattern_from_image(&half_white(), &ImportSettings::default()).unwrap();
+        assert_eq!(pattern.width(), 4);
+        assert_eq!(pattern.height(), 2);
+        assert_eq!(pattern.get(IVec2::new(1, 1)), Some(CellState::Cut));
+        assert_eq!(pattern.get(IVec2::new(2, 0)), Some(CellState::Standing));
+    }
+
+    #[test]
+    fn invert_cuts_the_dark_pixels() {
+        let settings = ImportSettings { invert: true, ..default() };
+        let pattern = pattern_from_image(&half_white(), &settings).unwrap();
+        assert_eq!(pattern.get(IVec2::new(1, 1)), Some(CellState::Standing));
+        assert_eq!(pattern.get(IVec2::new(2, 0)), Some(CellState::Cut));
+    }
+
+    #[test]
+    fn cells_average_their_pixels() {
+        // Every cell of a 2x1 pattern covers a white and a black pixel, half bright
+        let image = png(RgbaImage::from_fn(4, 1, |x, _| if x % 2 == 0 { Rgba([255; 4]) } else { Rgba([0, 0, 0, 255]) }));
+        let size = ImportSettings { width: 2, height: 1, ..default() };
+
+        let pattern = pattern_from_image(&image, &ImportSettings { threshold: 0.4, ..size }).unwrap();
+        assert_eq!(pattern.iter().filter(|(_, state)| *state == CellState::Cut).count(), 2);
+        let pattern = pattern_from_image(&image, &ImportSettings { threshold: 0.6, ..size }).unwrap();
+        assert_eq!(pattern.iter().filter(|(_, state)| *state == CellState::Cut).count(), 0);
+    }
+
+    #[test]
+    fn transparent_pixels_are_dark() {
+        let image = png(RgbaImage::from_pixel(2, 2, Rgba([255, 255, 255, 0])));
+        let pattern = pattern_from_image(&image, &ImportSettings::default()).unwrap();
+        assert!(pattern.iter().all(|(_, state)| state == CellState::Standing));
+    }
+
+    #[test]
+    fn image_smaller_than_the_pattern_is_refused() {
+        let settings = ImportSettings { width: 8, height: 2, ..default() };
+        assert!(matches!(pattern_from_image(&half_white(), &settings), Err(ImportError::TooSmall(4, 2, 8, 2))));
+    }
+
+    #[test]
+    fn source_images_give_the_campaign_patterns() {
+        for (image, size, level) in [
+            (&include_bytes!("../../assets/crop_lvl0_11.png")[..], 11, include_str!("../../assets/levels/level_1.level.ron")),
+            (&include_bytes!("../../assets/crop_lvl1_17.png")[..], 17, include_str!("../../assets/levels/level_2.level.ron")),
+        ] {
+            let settings = ImportSettings { width: size, height: size, ..default() };
+            let level: LevelPattern = ron::from_str(level).unwrap();
+            let pattern = pattern_from_image(image, &settings).unwrap();
+            for (y, line) in level.pattern.iter().enumerate() {
+                for (x, c) in line.chars().enumerate() {
+                    let cut = pattern.get(IVec2::new(x as i32, y as i32)) == Some(CellState::Cut);
+                    assert_eq!(cut, c == '0', "cell {x}x{y} of the {size}x{size} level");
+                }
+            }
+        }
+    }
+}
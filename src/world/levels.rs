@@ -2,146 +2,494 @@
  * Copyright (c) 2024 Elieva Pignat, Florian Depraz, Louis Mayencourt
  */
 
-pub const LEVEL_COUNT: usize = 4;
-
-pub struct Level<'a>{
-    pub grid_size: usize,
-    pub data: &'a str,
-    pub image: &'a str,
-    // accepted number of mistakes/forgotten to go to next level
-    pub mistake_level: u32,
-    pub forgotten_level: u32,
-}
-
-pub const LEVELS: [Level; LEVEL_COUNT] = [
-    Level{
-        grid_size: 11,
-        data: LEVEL_1,
-        image: "embedded://crop_lvl0_11.png",
-        mistake_level: 5,
-        forgotten_level: 5,
-    },
-    Level{
-        grid_size: 17,
-        data: LEVEL_2,
-        image: "embedded://crop_lvl1_17.png",
-        mistake_level: 8,
-        forgotten_level: 8, 
-    },
-    Level{
-        grid_size: 33,
-        data: LEVEL_3,
-        image: "embedded://crop_lvl2_33.png",
-        mistake_level: 18,
-        forgotten_level: 18,
-    },
-    Level{
-        grid_size: 33,
-        data: LEVEL_4,
-        image: "embedded://crop_lvl3_33.png",
-        mistake_level: 30,
-        forgotten_level: 30,
-    }
-];
-
-pub const LEVEL_1: &str =
-"11111111111
-11111111111
-11111111111
-11110001111
-11101110111
-11011011011
-11101110111
-11110001111
-11111111111
-11111111111
-11111111111";
-
-pub const LEVEL_2: &str = 
-"11111111111111111
-11111111111111111
-11111111111111111
-11111100000111111
-11111111011111111
-11111111011111111
-11111110001111111
-11111101110111111
-11111011011011111
-11111101110111111
-11111110001111111
-11111111011111111
-11111111011111111
-11111100000111111
-11111111111111111
-11111111111111111
-11111111111111111";
-
-pub const LEVEL_3: &str = 
-"111111111111111111111111111111111
-111111111111111111111111111111111
-111111111111111111111111111111111
-111111111111111111111111111111111
-111111111111111111111111111111111
-111111111111111101111111111111111
-111111111111111101111111111111111
-111111111111111101111111111111111
-111111111111100000001111111111111
-111111111110011111110011111111111
-111111111101110000011101111111111
-111111111011111101111110111111111
-111111110111111101111111011111111
-111111110111111101111111011111111
-111111101011111000111110101111111
-111111101011110111011110101111111
-111100001000001101100000100001111
-111111101011110111011110101111111
-111111101011111000111110101111111
-111111110111111101111111011111111
-111111110111111101111111011111111
-111111111011111101111110111111111
-111111111101110000011101111111111
-111111111110011111110011111111111
-111111111111100000001111111111111
-111111111111111101111111111111111
-111111111111111101111111111111111
-111111111111111101111111111111111
-111111111111111111111111111111111
-111111111111111111111111111111111
-111111111111111111111111111111111
-111111111111111111111111111111111
-111111111111111111111111111111111";
-
-pub const LEVEL_4: &str =
-"111111111111111111111111111111111
-111111111111111111111111111111111
-111111111111111111111111111111111
-111111111111111101111111111111111
-111110111111111101111111111011111
-111111011111100000001111110111111
-111111101100011100000001101111111
-111111110011111100000000011111111
-111111101111100000000000001111111
-111111011110011111110000000111111
-111110111101110000011100000011111
-111101111011111101111110000001111
-111101110111111101111111000001111
-111011110111111101111111000000111
-111011101011111000111110100000111
-111011101011110111011110100000111
-110000001000001101100000100000011
-111000001011110111011110101110111
-111000001011111000111110101110111
-111000000111111101111111011110111
-111100000111111101111111011101111
-111100000011111101111110111101111
-111110000001110000011101111011111
-111111000000011111110011110111111
-111111100000000000001111101111111
-111111110000000001111110011111111
-111111101100000001110001101111111
-111111011111100000001111110111111
-111110111111111101111111111011111
-111111111111111101111111111111111
-111111111111111111111111111111111
-111111111111111111111111111111111
-111111111111111111111111111111111";
\ No newline at end of file
+use bevy::{
+    asset::{io::Reader, AssetLoader, AsyncReadExt, LoadContext, RecursiveDependencyLoadState},
+    prelude::*,
+};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::GameState;
+use crate::brush::Brush;
+use crate::menu::{CurrentLevel, RestartGame};
+use crate::world::generator::PatternGenerator;
+use crate::world::grid::{CellState, FieldGrid};
+use crate::world::import::{pattern_from_image, ImportError, ImportSettings, LevelImageLoader};
+
+/// Biggest field accepted in a level file, in both directions
+pub const MAX_GRID_SIZE: usize = 256;
+
+/// Corn which can be replanted in a level that does not say otherwise
+pub const DEFAULT_SEEDS: u32 = 3;
+
+/// Accuracy needed for one, two and three stars in a level that does not say otherwise
+pub const DEFAULT_STARS: [f32; 3] = [0.6, 0.8, 0.95];
+
+/// Path of the campaign file listing all the levels, in play order
+#[cfg(not(feature = "hot_reload"))]
+pub const CAMPAIGN_PATH: &str = "embedded://levels/main.campaign.ron";
+
+/// Embedded assets are not watched, so with hot reloading the levels are read
+/// from the assets folder instead
+#[cfg(feature = "hot_reload")]
+pub const CAMPAIGN_PATH: &str = "levels/main.campaign.ron";
+
+/// A playable crop circle, loaded from a `.level.ron` file
+#[derive(Asset, TypePath, Debug)]
+pub struct Level {
+    /// Expected state of the field at the end of the level, its size is the size of the field
+    pub pattern: FieldGrid,
+    /// Seed of the random numbers used to build the field, to get the same field every time
+    pub seed: Option<u64>,
+    /// Accuracy needed for one, two and three stars, one star is enough to pass the level
+    pub stars: [f32; 3],
+    /// Number of cells the player can replant
+    pub seeds: u32,
+    /// Mistakes added to the score for every undo
+    pub undo_penalty: u32,
+    /// Brushes the player can use, the first one is selected at the start
+    pub brushes: Vec<Brush>,
+    /// The player can move in diagonal
+    pub diagonals: bool,
+}
+
+impl Level {
+    /// Level from a pattern, harder levels need a better accuracy
+    pub fn from_pattern(pattern: FieldGrid, difficulty: f32) -> Self {
+        // From 60% of accuracy to pass easy levels up to 80% for the hardest
+        let pass = 0.6 + 0.2 * difficulty.clamp(0.0, 1.0);
+
+        Self {
+            pattern,
+            seed: None,
+            stars: [pass, (pass + 1.0) / 2.0, DEFAULT_STARS[2]],
+            seeds: DEFAULT_SEEDS,
+            undo_penalty: 0,
+            // Generated and imported fields can be large, give all the brushes
+            brushes: Brush::ALL.to_vec(),
+            diagonals: true,
+        }
+    }
+
+    /// Level with a generated formation
+    pub fn generated(generator: PatternGenerator, width: usize, height: usize) -> Self {
+        Self {
+            seed: Some(generator.seed),
+            ..Self::from_pattern(generator.generate(width, height), generator.difficulty)
+        }
+    }
+
+    /// Intersection over union of the cells cut and the cells of the formation, from 0.0 to 1.0.
+    /// It does not depend on the size of the field, a mistake costs as much as a forgotten cell.
+    pub fn accuracy(&self, mistakes: u32, forgotten: u32) -> f32 {
+        let to_cut = self.cells_to_cut() as u32;
+        let union = to_cut + mistakes;
+        if union == 0 {
+            return 1.0;
+        }
+        to_cut.saturating_sub(forgotten) as f32 / union as f32
+    }
+
+    /// Number of stars earned with an accuracy, from 0 to 3
+    pub fn stars_for(&self, accuracy: f32) -> u32 {
+        self.stars.iter().filter(|threshold| accuracy >= **threshold).count() as u32
+    }
+
+    /// Number of cells to cut to complete the level
+    pub fn cells_to_cut(&self) -> usize {
+        self.pattern.iter().filter(|(_, state)| *state == CellState::Cut).count()
+    }
+}
+
+/// Ordered list of levels, loaded from a `.campaign.ron` file
+#[derive(Asset, TypePath, Debug)]
+pub struct Campaign {
+    pub levels: Vec<Handle<Level>>,
+}
+
+/// Handle on the campaign, kept alive for the whole game
+#[derive(Resource)]
+pub struct LevelList {
+    pub campaign: Handle<Campaign>,
+}
+
+/// Content of a `.level.ron` file.
+/// The pattern is one string per line of the field, '0' for the cells to cut,
+/// '1' for the corn to keep and 'X' for obstacles. Instead of a pattern, a level
+/// can give the parameters of a generated formation, or an image to import.
+/// The image path is relative to the level file.
+#[derive(Deserialize, Serialize)]
+pub struct LevelFile {
+    width: usize,
+    height: usize,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pattern: Option<Vec<String>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    generator: Option<PatternGenerator>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pattern_image: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    seed: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    stars: Option<[f32; 3]>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    seeds: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    undo_penalty: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    brushes: Option<Vec<Brush>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    diagonals: Option<bool>,
+}
+
+impl LevelFile {
+    /// File content describing the level with a pattern
+    pub fn from_level(level: &Level) -> Self {
+        let pattern = (0..level.pattern.height()).map(|y| {
+            (0..level.pattern.width()).map(|x| {
+                match level.pattern.get(IVec2::new(x as i32, y as i32)) {
+                    Some(CellState::Cut) => '0',
+                    Some(CellState::Obstacle) => 'X',
+                    _ => '1',
+                }
+            }).collect()
+        }).collect();
+
+        Self {
+            width: level.pattern.width(),
+            height: level.pattern.height(),
+            pattern: Some(pattern),
+            generator: None,
+            pattern_image: None,
+            seed: level.seed,
+            stars: Some(level.stars),
+            seeds: Some(level.seeds),
+            undo_penalty: Some(level.undo_penalty),
+            brushes: Some(level.brushes.clone()),
+            diagonals: Some(level.diagonals),
+        }
+    }
+}
+
+/// Content of a `.campaign.ron` file, level paths are relative to the campaign file
+#[derive(Deserialize)]
+struct CampaignFile {
+    levels: Vec<String>,
+}
+
+#[non_exhaustive]
+#[derive(Debug, Error)]
+pub enum LevelLoaderError {
+    #[error("Could not load level: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Could not parse RON: {0}")]
+    Ron(#[from] ron::error::SpannedError),
+    #[error("Invalid path: {0}")]
+    Path(#[from] bevy::asset::ParseAssetPathError),
+    #[error("Pattern is not {0}x{1}")]
+    PatternSize(usize, usize),
+    #[error("Level size {0}x{1} is not between 1x1 and {MAX_GRID_SIZE}x{MAX_GRID_SIZE}")]
+    LevelSize(usize, usize),
+    #[error("Pattern contains '{0}', only '0', '1' and 'X' are allowed")]
+    PatternChar(char),
+    #[error("Star thresholds {0:?} must increase and be between 0 and 1")]
+    Stars([f32; 3]),
+    #[error("Level needs one of pattern, generator or pattern_image")]
+    MissingPattern,
+    #[error("Could not read pattern image: {0}")]
+    ReadImage(#[from] bevy::asset::ReadAssetBytesError),
+    #[error("Could not import pattern image: {0}")]
+    Import(#[from] ImportError),
+}
+
+#[derive(Default)]
+struct LevelLoader;
+
+impl AssetLoader for LevelLoader {
+    type Asset = Level;
+    type Settings = ();
+    type Error = LevelLoaderError;
+
+    async fn load<'a>(
+        &'a self,
+        reader: &'a mut Reader<'_>,
+        _settings: &'a (),
+        load_context: &'a mut LoadContext<'_>,
+    ) -> Result<Self::Asset, Self::Error> {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes).await?;
+        // The optional fields are written without `Some(...)` in the level files
+        let file = ron::Options::default()
+            .with_default_extension(ron::extensions::Extensions::IMPLICIT_SOME)
+            .from_bytes::<LevelFile>(&bytes)?;
+
+        if file.width == 0 || file.height == 0 || file.width > MAX_GRID_SIZE || file.height > MAX_GRID_SIZE {
+            return Err(LevelLoaderError::LevelSize(file.width, file.height));
+        }
+        let pattern = match (&file.pattern, &file.generator, &file.pattern_image) {
+            (Some(lines), None, None) => parse_pattern(lines, file.width, file.height)?,
+            (None, Some(generator), None) => generator.generate(file.width, file.height),
+            (None, None, Some(image)) => {
+                let image_path = load_context.asset_path().resolve_embed(image)?;
+                let bytes = load_context.read_asset_bytes(image_path).await?;
+                let settings = ImportSettings {
+                    width: file.width,
+                    height: file.height,
+                    ..default()
+                };
+                pattern_from_image(&bytes, &settings)?
+            }
+            _ => return Err(LevelLoaderError::MissingPattern),
+        };
+
+        let stars = file.stars.unwrap_or(DEFAULT_STARS);
+        if stars.iter().any(|threshold| !(0.0..=1.0).contains(threshold)) || stars[0] > stars[1] || stars[1] > stars[2] {
+            return Err(LevelLoaderError::Stars(stars));
+        }
+
+        Ok(Level {
+            pattern,
+            seed: file.seed.or(file.generator.map(|generator| generator.seed)),
+            stars,
+            seeds: file.seeds.unwrap_or(DEFAULT_SEEDS),
+            undo_penalty: file.undo_penalty.unwrap_or(0),
+            brushes: match file.brushes {
+                Some(brushes) if !brushes.is_empty() => brushes,
+                _ => vec![Brush::Single],
+            },
+            diagonals: file.diagonals.unwrap_or(true),
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["level.ron"]
+    }
+}
+
+/// Read the pattern of a level file
+fn parse_pattern(lines: &[String], width: usize, height: usize) -> Result<FieldGrid, LevelLoaderError> {
+    if lines.len() != height || lines.iter().any(|line| line.chars().count() != width) {
+        return Err(LevelLoaderError::PatternSize(width, height));
+    }
+
+    let mut pattern = FieldGrid::new(width, height);
+    for (y, line) in lines.iter().enumerate() {
+        for (x, c) in line.chars().enumerate() {
+            let state = match c {
+                '0' => CellState::Cut,
+                '1' => CellState::Standing,
+                'X' => CellState::Obstacle,
+                _ => return Err(LevelLoaderError::PatternChar(c)),
+            };
+            pattern.set(IVec2::new(x as i32, y as i32), state);
+        }
+    }
+    Ok(pattern)
+}
+
+#[derive(Default)]
+struct CampaignLoader;
+
+impl AssetLoader for CampaignLoader {
+    type Asset = Campaign;
+    type Settings = ();
+    type Error = LevelLoaderError;
+
+    async fn load<'a>(
+        &'a self,
+        reader: &'a mut Reader<'_>,
+        _settings: &'a (),
+        load_context: &'a mut LoadContext<'_>,
+    ) -> Result<Self::Asset, Self::Error> {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes).await?;
+        let file = ron::de::from_bytes::<CampaignFile>(&bytes)?;
+
+        let mut levels = Vec::new();
+        for level in file.levels {
+            let level_path = load_context.asset_path().resolve_embed(&level)?;
+            levels.push(load_context.load(level_path));
+        }
+
+        Ok(Campaign { levels })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["campaign.ron"]
+    }
+}
+
+/// Plugin loading the levels, the game leaves `GameState::Loading` once they are all available
+pub struct LevelsPlugin;
+
+impl Plugin for LevelsPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_asset::<Level>();
+        app.init_asset::<Campaign>();
+        app.init_asset_loader::<LevelLoader>();
+        app.init_asset_loader::<CampaignLoader>();
+        app.init_asset_loader::<LevelImageLoader>();
+        app.add_systems(Startup, load_levels);
+        app.add_systems(Update, wait_for_levels.run_if(in_state(GameState::Loading)));
+        app.add_systems(Update, reload_levels.run_if(not(in_state(GameState::Loading))));
+    }
+}
+
+fn load_levels(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+) {
+    commands.insert_resource(LevelList {
+        campaign: asset_server.load(CAMPAIGN_PATH),
+    });
+}
+
+fn wait_for_levels(
+    asset_server: Res<AssetServer>,
+    level_list: Res<LevelList>,
+    campaigns: Res<Assets<Campaign>>,
+    mut current_level: ResMut<CurrentLevel>,
+    mut next_state: ResMut<NextState<GameState>>,
+    mut restart: EventWriter<RestartGame>,
+    mut reported: Local<bool>,
+) {
+    match asset_server.recursive_dependency_load_state(&level_list.campaign) {
+        RecursiveDependencyLoadState::Loaded => (),
+        RecursiveDependencyLoadState::Failed => {
+            if !*reported {
+                error!("Failed to load the levels from {}", CAMPAIGN_PATH);
+                *reported = true;
+            }
+            return;
+        }
+        _ => return,
+    }
+
+    let Some(campaign) = campaigns.get(&level_list.campaign) else {
+        return;
+    };
+
+    if campaign.levels.is_empty() {
+        error!("The campaign does not contain any level");
+        return;
+    }
+
+    info!("{} levels loaded", campaign.levels.len());
+    current_level.select(0, campaign);
+    // Build the board of the first level
+    restart.send_default();
+    next_state.set(GameState::LandingScreen);
+}
+
+/// Rebuild the board when the current level or the campaign is modified on disk
+fn reload_levels(
+    mut level_events: EventReader<AssetEvent<Level>>,
+    mut campaign_events: EventReader<AssetEvent<Campaign>>,
+    level_list: Res<LevelList>,
+    campaigns: Res<Assets<Campaign>>,
+    mut current_level: ResMut<CurrentLevel>,
+    mut restart: EventWriter<RestartGame>,
+) {
+    let mut reload = false;
+
+    for event in campaign_events.read() {
+        if !event.is_modified(&level_list.campaign) {
+            continue;
+        }
+        let Some(campaign) = campaigns.get(&level_list.campaign) else {
+            continue;
+        };
+        if campaign.levels.is_empty() {
+            error!("The campaign does not contain any level");
+            continue;
+        }
+        // Levels may have been removed or reordered, keep the same position if it still exists
+        let idx = current_level.idx.min(campaign.levels.len() - 1);
+        current_level.select(idx, campaign);
+        reload = true;
+    }
+
+    for event in level_events.read() {
+        if event.is_modified(&current_level.handle) {
+            reload = true;
+        }
+    }
+
+    if reload {
+        info!("Level {} modified, rebuilding the field", current_level.idx + 1);
+        restart.send_default();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lines(pattern: &[&str]) -> Vec<String> {
+        pattern.iter().map(|line| line.to_string()).collect()
+    }
+
+    #[test]
+    fn parse_pattern_reads_every_cell() {
+        let pattern = parse_pattern(&lines(&["01X", "100"]), 3, 2).unwrap();
+        assert_eq!(pattern.get(IVec2::new(0, 0)), Some(CellState::Cut));
+        assert_eq!(pattern.get(IVec2::new(1, 0)), Some(CellState::Standing));
+        assert_eq!(pattern.get(IVec2::new(2, 0)), Some(CellState::Obstacle));
+        assert_eq!(pattern.get(IVec2::new(0, 1)), Some(CellState::Standing));
+        assert_eq!(pattern.get(IVec2::new(2, 1)), Some(CellState::Cut));
+    }
+
+    #[test]
+    fn parse_pattern_rejects_a_wrong_size() {
+        let too_few_lines = parse_pattern(&lines(&["010"]), 3, 2);
+        assert!(matches!(too_few_lines, Err(LevelLoaderError::PatternSize(3, 2))));
+
+        let short_line = parse_pattern(&lines(&["010", "01"]), 3, 2);
+        assert!(matches!(short_line, Err(LevelLoaderError::PatternSize(3, 2))));
+    }
+
+    #[test]
+    fn parse_pattern_rejects_an_unknown_char() {
+        let pattern = parse_pattern(&lines(&["01", "0x"]), 2, 2);
+        assert!(matches!(pattern, Err(LevelLoaderError::PatternChar('x'))));
+    }
+
+    fn level_to_cut(cells: i32) -> Level {
+        let mut pattern = FieldGrid::new(10, 1);
+        for x in 0..cells {
+            pattern.set(IVec2::new(x, 0), CellState::Cut);
+        }
+        Level {
+            stars: [0.5, 0.75, 0.95],
+            ..Level::from_pattern(pattern, 0.0)
+        }
+    }
+
+    #[test]
+    fn accuracy_of_a_level() {
+        let level = level_to_cut(4);
+        assert_eq!(level.cells_to_cut(), 4);
+        assert_eq!(level.accuracy(0, 0), 1.0);
+        assert_eq!(level.accuracy(0, 1), 0.75);
+        assert_eq!(level.accuracy(4, 0), 0.5);
+        assert_eq!(level.accuracy(1, 2), 0.4);
+        assert_eq!(level.accuracy(0, 4), 0.0);
+    }
+
+    #[test]
+    fn accuracy_of_a_level_without_cells_to_cut() {
+        let level = level_to_cut(0);
+        assert_eq!(level.accuracy(0, 0), 1.0);
+        assert_eq!(level.accuracy(2, 0), 0.0);
+    }
+
+    #[test]
+    fn stars_for_an_accuracy() {
+        let level = level_to_cut(4);
+        assert_eq!(level.stars_for(0.4), 0);
+        assert_eq!(level.stars_for(0.5), 1);
+        assert_eq!(level.stars_for(0.8), 2);
+        assert_eq!(level.stars_for(0.95), 3);
+        assert_eq!(level.stars_for(1.0), 3);
+    }
+}
@@ -2,13 +2,18 @@
 * Copyright (c) 2024 Elieva Pignat, Florian Depraz, Louis Mayencourt
 */
 
+use std::collections::HashMap;
+
 use bevy::prelude::*;
 // use bevy_hanabi::prelude::*;
 
 use crate::GameState;
 use crate::menu::GameScore;
-use crate::world::{Corn, levels::{LEVELS, LEVEL_COUNT}};
-use crate::menu::{CurrentLevel, RestartGame};
+use crate::world::grid::{CellState, FieldGrid};
+use crate::menu::RestartGame;
+
+/// Number of lights along the longest side of the field, when the crop circle is lit up
+const MAX_LIGHTS_PER_SIDE: usize = 8;
 
 #[derive(Component)]
 struct CropCircleLights;
@@ -27,43 +32,58 @@ impl Plugin for CropCircleLightsPlugin {
 }
 
 fn draw_lights(
-    event: EventReader<ShowLights>,
+    mut event: EventReader<ShowLights>,
     mut commands: Commands,
     mut meshes: ResMut<Assets<Mesh>>,
     mut materials: ResMut<Assets<StandardMaterial>>,
-    current_level: Res<CurrentLevel>,
+    field: Res<FieldGrid>,
 ) {
     let COLOR_LIGHT_SCALE: Color = Color::srgb(0.0, 0.0, 1.0);
     if !event.is_empty() {
-            for (y, line) in LEVELS[current_level.idx].data.lines().enumerate() {
-                for (x, char) in line.chars().enumerate() {
-                    if char == '0' {
-                        commands.spawn((PbrBundle {
-                            mesh: meshes.add(Cuboid::new(1.0, 0.2, 1.0)),
-                            material: materials.add(Color::srgba(0.0, 0.0, 1.0, 0.4)),
-                            transform: Transform::from_xyz(x as f32, 1.2, y as f32),
-                            ..default()
-                            },
-                            CropCircleLights
-                        // ));
-                        )).with_children(
-                            |children| {
-                                children.spawn(PointLightBundle {
-                                    point_light: PointLight {
-                                        color: COLOR_LIGHT_SCALE,
-                                        intensity: 1_000_000.0,
-                                        range: 4.0,
-                                        ..default()
-                                    },
-                                    transform: Transform::from_xyz(0.0, 1.2, 0.0),
-                                    ..default()
-                                });
-                            });
+        event.clear();
 
-                    }
-                }
+        // Light up the crop circle drawn by the player, the tiles share their mesh and material
+        let mesh = meshes.add(Cuboid::new(1.0, 0.2, 1.0));
+        let material = materials.add(Color::srgba(0.0, 0.0, 1.0, 0.4));
+        let region = light_region(&field);
+        let mut regions: HashMap<IVec2, Vec<IVec2>> = HashMap::new();
+        for (cell, state) in field.iter() {
+            if state == CellState::Cut {
+                commands.spawn((PbrBundle {
+                    mesh: mesh.clone(),
+                    material: material.clone(),
+                    transform: Transform::from_xyz(cell.x as f32, 1.2, cell.y as f32),
+                    ..default()
+                    },
+                    CropCircleLights
+                ));
+                regions.entry(cell / region).or_default().push(cell);
             }
         }
+
+        // A point light per cell is too much for big fields, one lights a whole region
+        for cells in regions.values() {
+            let center = cells.iter().map(|cell| cell.as_vec2()).sum::<Vec2>() / cells.len() as f32;
+            commands.spawn((PointLightBundle {
+                point_light: PointLight {
+                    color: COLOR_LIGHT_SCALE,
+                    intensity: 1_000_000.0 * (cells.len() as f32).sqrt(),
+                    range: 4.0 + region as f32,
+                    ..default()
+                },
+                transform: Transform::from_xyz(center.x, 2.4, center.y),
+                ..default()
+                },
+                CropCircleLights
+            ));
+        }
+    }
+}
+
+/// Size of the square of cells lit by a single light, the whole field has at most
+/// `MAX_LIGHTS_PER_SIDE` x `MAX_LIGHTS_PER_SIDE` lights
+fn light_region(field: &FieldGrid) -> i32 {
+    (field.width().max(field.height()).div_ceil(MAX_LIGHTS_PER_SIDE) as i32).max(2)
 }
 
 fn remove_lights(
@@ -76,4 +96,18 @@ fn remove_lights(
             commands.entity(light).despawn_recursive();
         }
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::world::levels::MAX_GRID_SIZE;
+
+    #[test]
+    fn lights_are_capped_on_big_fields() {
+        assert_eq!(light_region(&FieldGrid::new(11, 11)), 2);
+        for size in [64, 128, 200, MAX_GRID_SIZE] {
+            let region = light_region(&FieldGrid::new(size, size / 2)) as usize;
+            assert!(size.div_ceil(region) <= MAX_LIGHTS_PER_SIDE, "{size} is lit by regions of {region}");
+        }
+    }
+}
@@ -7,12 +7,18 @@ use std::isize;
 use bevy::prelude::*;
 use rand::Rng; // 0.8.5
 
+use crate::rng::GameRng;
 use crate::menu::{RestartGame, CurrentLevel};
-use crate::world::levels::LEVELS;
+use crate::world::grid::{CellState, FieldGrid};
+use crate::world::levels::Level;
 
+pub mod generator;
+pub mod grid;
+pub mod import;
 pub mod levels;
 pub mod lights;
 
+use levels::LevelsPlugin;
 use lights::CropCircleLightsPlugin;
 
 /// Size of the world and game grid
@@ -21,12 +27,32 @@ pub const WORLD_OFFSET_OF_GRID: isize = 5;
 pub const YELLOW: Color = Color::srgb(234.0 / 255.0, 189.0 / 255.0, 71.0 / 255.0);
 pub const MILESTONE_COLOR: Color = Color::srgb(155.0/255.0, 34.0/255.0, 38.0/255.0);
 pub const SCALE_COLOR: Color = Color::srgb(34.0/255.0, 34.0/255.0, 255.0/255.0);
+pub const OBSTACLE_COLOR: Color = Color::srgb(0.4, 0.4, 0.4);
 const INTENSITY_LIGHT: f32 = 100_000.0;
 const RANGE_LIGHT : f32= 4.0;
 
-/// Component to identify the Corn
+/// Component to identify the Corn, a view of a standing cell of the `FieldGrid`
 #[derive(Component)]
-pub struct Corn;
+pub struct Corn {
+    pub cell: IVec2,
+}
+
+/// Sent when the player changes the state of a cell of the `FieldGrid`
+#[derive(Event)]
+pub struct CellChanged {
+    pub cell: IVec2,
+}
+
+/// Component to identify the obstacles of the field
+#[derive(Component)]
+pub struct Obstacle;
+
+/// Mesh and material shared by all the corn entities
+#[derive(Resource)]
+struct CornAssets {
+    mesh: Handle<Mesh>,
+    material: Handle<StandardMaterial>,
+}
 
 /// Component to identify the Grid Floor
 #[derive(Component)]
@@ -36,27 +62,43 @@ pub struct Floor;
 #[derive(Component)]
 pub struct Marker;
 
+/// Systems rebuilding the board when the game is restarted
+#[derive(SystemSet, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct WorldReset;
+
 /// Plugin to be included in main application
 pub struct WorldPlugin;
 
 impl Plugin for WorldPlugin {
     fn build(&self, app: &mut App) {
+        app.init_resource::<FieldGrid>();
+        app.add_event::<CellChanged>();
         app.add_systems(Startup, setup_world);
-        app.add_systems(Update, reset_world);
+        // The corn must be despawned before the view is synced with the new field
+        app.add_systems(Update, (reset_world.in_set(WorldReset), sync_corn).chain());
         app.add_plugins(CropCircleLightsPlugin);
+        app.add_plugins(LevelsPlugin);
     }
 }
 
-/// System to spawn all the entities for the game world
+/// System to spawn the fixed entities of the game world.
+/// The board itself is spawned by `reset_world` once a level is selected.
 fn setup_world(
     mut commands: Commands,
     mut meshes: ResMut<Assets<Mesh>>,
     mut materials: ResMut<Assets<StandardMaterial>>,
-    current_level: Res<CurrentLevel>,
 ) {
-    let level_size = LEVELS[current_level.idx].grid_size as f32;
     let COLOR_LIGHT_MILESTONE: Color = Color::srgb(1.0, 0.0, 0.0);
 
+    commands.insert_resource(CornAssets {
+        mesh: meshes.add(Cuboid::new(1.0, 1.0, 1.0)),
+        material: materials.add(StandardMaterial {
+            reflectance: 0.00,
+            unlit: false,
+            base_color: YELLOW,
+        ..Default::default()}),
+    });
+
     // 0 0 marker stone
     commands.spawn(PbrBundle {
         mesh: meshes.add(Cuboid::new(0.2, 2.0, 0.2)),
@@ -77,21 +119,30 @@ fn setup_world(
                 ..default()
             });
         });
-
-    spawn_board(commands, meshes, materials, current_level);
 }
 
 fn reset_world(
-    event: EventReader<RestartGame>,
+    mut event: EventReader<RestartGame>,
     mut commands: Commands,
-    mut meshes: ResMut<Assets<Mesh>>,
-    mut materials: ResMut<Assets<StandardMaterial>>,
+    meshes: ResMut<Assets<Mesh>>,
+    materials: ResMut<Assets<StandardMaterial>>,
     corns: Query<Entity, With<Corn>>,
     markers: Query<Entity, With<Marker>>,
     floors: Query<Entity, With<Floor>>,
+    obstacles: Query<Entity, With<Obstacle>>,
     current_level: Res<CurrentLevel>,
+    levels: Res<Assets<Level>>,
+    mut field: ResMut<FieldGrid>,
+    corn_assets: Res<CornAssets>,
+    mut rng: ResMut<GameRng>,
 ) {
     if !event.is_empty() {
+        // Rebuild the board only once, even if several restarts were requested
+        event.clear();
+        let Some(level) = levels.get(&current_level.handle) else {
+            return;
+        };
+
         for corn in corns.iter() {
             commands.entity(corn).despawn_recursive();
         }
@@ -104,7 +155,26 @@ fn reset_world(
             commands.entity(floor).despawn_recursive();
         }
 
-        spawn_board(commands, meshes, materials, current_level);
+        for obstacle in obstacles.iter() {
+            commands.entity(obstacle).despawn_recursive();
+        }
+
+        // Seeded levels always get the same floor and corn heights
+        if let Some(seed) = level.seed {
+            rng.reseed(seed);
+        }
+
+        *field = FieldGrid::start_of(&level.pattern);
+
+        // The corn is spawned here rather than by sync_corn, to take the random
+        // heights right after the floor, before any other system uses the generator
+        for (cell, state) in field.iter() {
+            if state == CellState::Standing {
+                spawn_corn(&mut commands, &corn_assets, cell, &mut rng);
+            }
+        }
+
+        spawn_board(commands, meshes, materials, level, &mut rng);
     }
 }
 
@@ -112,24 +182,31 @@ fn spawn_board(
     mut commands: Commands,
     mut meshes: ResMut<Assets<Mesh>>,
     mut materials: ResMut<Assets<StandardMaterial>>,
-    current_level: Res<CurrentLevel>,
+    level: &Level,
+    rng: &mut GameRng,
 ) {
-    let level_size = LEVELS[current_level.idx].grid_size as f32;
+    let width = level.pattern.width() as isize;
+    let height = level.pattern.height() as isize;
+
+    // Share the meshes and materials between the tiles, big fields have thousands of them
+    let grass_mesh = meshes.add(Cuboid::new(1.0, 0.2, 1.0));
+    let grass_material = materials.add(Color::srgb(0.3, 0.5, 0.3));
+    let soil_mesh = meshes.add(Cuboid::new(1.0, 0.3, 1.0));
+    let soil_material = materials.add(Color::srgba_u8(53, 33, 0, 255));
 
-    let floor_size = level_size as isize + WORLD_OFFSET_OF_GRID;
     let world_offset_of_grid = -WORLD_OFFSET_OF_GRID;
     // This is the floor of the game, adding 2 tiles of margin
-    for x in world_offset_of_grid..floor_size {
-        for z in world_offset_of_grid..floor_size {
+    for x in world_offset_of_grid..width + WORLD_OFFSET_OF_GRID {
+        for z in world_offset_of_grid..height + WORLD_OFFSET_OF_GRID {
 
-            let num = rand::thread_rng().gen_range(0.0..0.10);
+            let num = rng.gen_range(0.0..0.10);
 
-            if (x < 0) || (z < 0) || (z >= level_size as isize) || (x >= level_size as isize)
+            if (x < 0) || (z < 0) || (z >= height) || (x >= width)
             {
                 commands.spawn((
                     PbrBundle {
-                        mesh: meshes.add(Cuboid::new(1.0, 0.2, 1.0)),
-                        material: materials.add(Color::srgb(0.3, 0.5, 0.3)),
+                        mesh: grass_mesh.clone(),
+                        material: grass_material.clone(),
                         transform: Transform::from_xyz(x as f32, -num,  z as f32),
                         ..default()
                         },
@@ -140,8 +217,8 @@ fn spawn_board(
             {
                 commands.spawn((
                     PbrBundle {
-                        mesh: meshes.add(Cuboid::new(1.0, 0.3, 1.0)),
-                        material: materials.add(Color::srgba_u8(53, 33, 0, 255)),
+                        mesh: soil_mesh.clone(),
+                        material: soil_material.clone(),
                         transform: Transform::from_xyz(x as f32, -num,  z as f32),
                         ..default()
                         },
@@ -151,24 +228,16 @@ fn spawn_board(
         }
     }
 
-    // We need apparently to work on the X - Z plane, Y being the height for us.
-    for x in 0..level_size as usize {
-        for z in 0..level_size as usize {
-
-            let num = rand::thread_rng().gen_range(0.45..0.55);
-
+    for (cell, state) in level.pattern.iter() {
+        if state == CellState::Obstacle {
             commands.spawn((
                 PbrBundle {
-                    mesh: meshes.add(Cuboid::new(1.0, 1.0, 1.0)),
-                    material: materials.add(StandardMaterial {
-                        reflectance: 0.00,
-                        unlit: false,
-                        base_color: YELLOW,
-                    ..Default::default()}),
-                    transform: Transform::from_xyz(x as f32, num,  z as f32),
+                    mesh: meshes.add(Cuboid::new(0.8, 0.8, 0.8)),
+                    material: materials.add(OBSTACLE_COLOR),
+                    transform: Transform::from_xyz(cell.x as f32, 0.4, cell.y as f32),
                     ..default()
                     },
-                Corn,
+                Obstacle,
                 ));
         }
     }
@@ -181,7 +250,7 @@ fn spawn_board(
         PbrBundle {
             mesh: meshes.add(Cuboid::new(0.2, 1.0, 0.2)),
             material: materials.add(SCALE_COLOR),
-            transform: Transform::from_xyz((level_size - 1.0) / 2.0, 1.0, -1.0),
+            transform: Transform::from_xyz((width as f32 - 1.0) / 2.0, 1.0, -1.0),
             ..default()
         },
         Marker,
@@ -205,7 +274,7 @@ fn spawn_board(
         PbrBundle {
             mesh: meshes.add(Cuboid::new(0.2, 1.0, 0.2)),
             material: materials.add(SCALE_COLOR),
-            transform: Transform::from_xyz(-1.0, 1.0, (level_size - 1.0) / 2.0),
+            transform: Transform::from_xyz(-1.0, 1.0, (height as f32 - 1.0) / 2.0),
             ..default()
         },
         Marker,
@@ -222,4 +291,59 @@ fn spawn_board(
                 ..default()
             });
         });
-}
\ No newline at end of file
+}
+
+/// Keep the corn entities in sync with the `FieldGrid`.
+/// Corn is despawned from every cell which is not standing anymore, and spawned
+/// on the standing cells that have none.
+fn sync_corn(
+    mut commands: Commands,
+    field: Res<FieldGrid>,
+    corns: Query<(Entity, &Corn)>,
+    corn_assets: Res<CornAssets>,
+    mut rng: ResMut<GameRng>,
+) {
+    if !field.is_changed() {
+        return;
+    }
+
+    let mut has_corn = vec![false; field.width() * field.height()];
+    for (entity, corn) in corns.iter() {
+        if field.get(corn.cell) == Some(CellState::Standing) {
+            let idx = corn.cell.y as usize * field.width() + corn.cell.x as usize;
+            if !has_corn[idx] {
+                has_corn[idx] = true;
+                continue;
+            }
+        }
+        commands.entity(entity).despawn();
+    }
+
+    for (cell, state) in field.iter() {
+        let idx = cell.y as usize * field.width() + cell.x as usize;
+        if state == CellState::Standing && !has_corn[idx] {
+            spawn_corn(&mut commands, &corn_assets, cell, &mut rng);
+        }
+    }
+}
+
+/// Spawn the corn of a cell, with a random height
+fn spawn_corn(
+    commands: &mut Commands,
+    corn_assets: &CornAssets,
+    cell: IVec2,
+    rng: &mut GameRng,
+) {
+    let num = rng.gen_range(0.45..0.55);
+
+    // We need apparently to work on the X - Z plane, Y being the height for us.
+    commands.spawn((
+        PbrBundle {
+            mesh: corn_assets.mesh.clone(),
+            material: corn_assets.material.clone(),
+            transform: Transform::from_xyz(cell.x as f32, num,  cell.y as f32),
+            ..default()
+            },
+        Corn { cell },
+        ));
+}